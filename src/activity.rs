@@ -0,0 +1,77 @@
+use rusqlite::{Connection, Row};
+use serde::Serialize;
+
+/// An item's name and status on one side of a logged change.
+#[derive(Debug, Serialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub is_shopped: bool,
+}
+
+/// One row of the `audit_log` table, written by triggers on
+/// `shopping_items` so that every change is logged in its own transaction.
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub id: i64,
+    /// `add`, `update` or `delete`.
+    pub op: String,
+    pub item_id: i64,
+    pub at: String,
+    /// `None` for additions.
+    pub before: Option<Snapshot>,
+    /// `None` for deletions.
+    pub after: Option<Snapshot>,
+}
+
+fn snapshot(row: &Row, name: usize, is_shopped: usize) -> rusqlite::Result<Option<Snapshot>> {
+    let name: Option<String> = row.get(name)?;
+    Ok(match name {
+        Some(name) => Some(Snapshot {
+            name,
+            is_shopped: row.get(is_shopped)?,
+        }),
+        None => None,
+    })
+}
+
+const ENTRY_COLUMNS: &str =
+    "id, op, item_id, at, name_before, shopped_before, name_after, shopped_after";
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<Entry> {
+    Ok(Entry {
+        id: row.get(0)?,
+        op: row.get(1)?,
+        item_id: row.get(2)?,
+        at: row.get(3)?,
+        before: snapshot(row, 4, 5)?,
+        after: snapshot(row, 6, 7)?,
+    })
+}
+
+/// The latest `limit` entries, newest first.
+pub fn recent(conn: &Connection, limit: u32) -> rusqlite::Result<Vec<Entry>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM audit_log ORDER BY id DESC LIMIT ?1",
+        ENTRY_COLUMNS
+    ))?;
+    stmt.query_map([limit], entry_from_row)?.collect()
+}
+
+/// Every entry still kept for one item, oldest first. Entries outlive the
+/// item itself, so a deleted item's history can still be read.
+pub fn for_item(conn: &Connection, item_id: i32) -> rusqlite::Result<Vec<Entry>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM audit_log WHERE item_id = ?1 ORDER BY id",
+        ENTRY_COLUMNS
+    ))?;
+    stmt.query_map([item_id], entry_from_row)?.collect()
+}
+
+/// Deletes entries older than `retention_days`, returning how many.
+pub fn prune(conn: &Connection, retention_days: u32) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM audit_log
+         WHERE at < strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?1)",
+        [format!("-{} days", retention_days)],
+    )
+}
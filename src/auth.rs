@@ -0,0 +1,41 @@
+use crate::AppState;
+use crate::error::ApiError;
+use actix_web::dev::Payload;
+use actix_web::http::header::HeaderName;
+use actix_web::{FromRequest, HttpRequest, web};
+use std::future::{Ready, ready};
+
+pub const X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Guards `/admin` endpoints: the request must carry the configured
+/// `API_KEY` in an `X-Api-Key` header. Without a configured key the admin
+/// endpoints are disabled entirely.
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let expected = req
+            .app_data::<web::Data<AppState>>()
+            .and_then(|state| state.api_key.as_deref());
+        let Some(expected) = expected else {
+            return ready(Err(ApiError::Forbidden(
+                "Admin endpoints are disabled, set API_KEY to enable them".to_string(),
+            )));
+        };
+
+        let provided = req.headers().get(X_API_KEY).map(|value| value.as_bytes());
+        ready(match provided {
+            Some(provided) if constant_time_eq(provided, expected.as_bytes()) => Ok(Admin),
+            _ => Err(ApiError::Unauthorized),
+        })
+    }
+}
+
+/// Compares without short-circuiting, so response timing doesn't reveal how
+/// much of a guessed key was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,98 @@
+use crate::{db, logging};
+use actix_web::rt::task;
+use log::{error, info};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+
+const PREFIX: &str = "shopping_list-";
+const SUFFIX: &str = ".db";
+
+/// Periodic copies of the database, enabled by `BACKUP_DIR`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub dir: PathBuf,
+    pub interval: Duration,
+    /// Number of backups to keep; older ones are deleted.
+    pub keep: usize,
+}
+
+/// File name for a backup taken now, e.g. `shopping_list-20261014T192400Z.db`.
+/// Names sort in the order the backups were taken.
+fn file_name() -> String {
+    let now = time::OffsetDateTime::now_utc();
+    format!(
+        "{}{:04}{:02}{:02}T{:02}{:02}{:02}Z{}",
+        PREFIX,
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        SUFFIX
+    )
+}
+
+/// Writes a consistent copy of the database with `VACUUM INTO`, which works
+/// while other connections keep writing.
+fn create(db_path: &str, dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let path = dir.join(file_name());
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| format!("backup path {} is not valid UTF-8", path.display()))?;
+    let conn = db::open(db_path)?;
+    conn.busy_timeout(Duration::from_secs(30))?;
+    conn.execute("VACUUM INTO ?1", [path_str])?;
+    Ok(path)
+}
+
+/// Deletes all but the newest `keep` backups in `dir`, returning how many
+/// were removed. Other files in the directory are left alone.
+fn prune(dir: &Path, keep: usize) -> io::Result<usize> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(SUFFIX))
+        })
+        .collect();
+    backups.sort();
+
+    let excess = backups.len().saturating_sub(keep);
+    for path in &backups[..excess] {
+        fs::remove_file(path)?;
+    }
+    Ok(excess)
+}
+
+/// Takes a backup right away and then every `config.interval`. Failures are
+/// logged and retried at the next interval.
+pub async fn run(db_path: &'static str, config: Config) {
+    let mut interval = actix_web::rt::time::interval(config.interval);
+    loop {
+        interval.tick().await;
+        let config = config.clone();
+        let result = task::spawn_blocking(move || {
+            let path = create(db_path, &config.dir)?;
+            let pruned = prune(&config.dir, config.keep)?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>((path, pruned))
+        })
+        .await;
+        match result {
+            Ok(Ok((path, pruned))) => info!(
+                target: logging::BACKUP,
+                "Backed up database to {}, removed {} old backups",
+                path.display(),
+                pruned
+            ),
+            Ok(Err(e)) => error!(target: logging::BACKUP, "Database backup failed: {}", e),
+            Err(e) => error!(
+                target: logging::BACKUP,
+                "Database backup task failed: {}", e
+            ),
+        }
+    }
+}
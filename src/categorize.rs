@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::{fs, io};
+
+/// How items without a category are reported and addressed, as in
+/// `PUT /items/category/uncategorized/shopped`. They are stored with a NULL
+/// category, never with this name.
+pub const UNCATEGORIZED: &str = "uncategorized";
+
+/// Keywords known without a `CATEGORY_KEYWORDS_PATH` file.
+const BUILT_IN: &[(&str, &str)] = &[
+    ("milk", "dairy"),
+    ("butter", "dairy"),
+    ("cheese", "dairy"),
+    ("yogurt", "dairy"),
+    ("yoghurt", "dairy"),
+    ("cream", "dairy"),
+    ("egg", "dairy"),
+    ("apple", "produce"),
+    ("banana", "produce"),
+    ("orange", "produce"),
+    ("lemon", "produce"),
+    ("grape", "produce"),
+    ("berry", "produce"),
+    ("berries", "produce"),
+    ("strawberries", "produce"),
+    ("tomato", "produce"),
+    ("potato", "produce"),
+    ("onion", "produce"),
+    ("garlic", "produce"),
+    ("carrot", "produce"),
+    ("lettuce", "produce"),
+    ("salad", "produce"),
+    ("cucumber", "produce"),
+    ("pepper", "produce"),
+    ("bread", "bakery"),
+    ("roll", "bakery"),
+    ("bagel", "bakery"),
+    ("croissant", "bakery"),
+    ("cake", "bakery"),
+    ("chicken", "meat"),
+    ("beef", "meat"),
+    ("pork", "meat"),
+    ("ham", "meat"),
+    ("bacon", "meat"),
+    ("sausage", "meat"),
+    ("mince", "meat"),
+    ("fish", "seafood"),
+    ("salmon", "seafood"),
+    ("tuna", "seafood"),
+    ("shrimp", "seafood"),
+    ("prawn", "seafood"),
+    ("pizza", "frozen"),
+    ("ice", "frozen"),
+    ("water", "beverages"),
+    ("juice", "beverages"),
+    ("coffee", "beverages"),
+    ("tea", "beverages"),
+    ("beer", "beverages"),
+    ("wine", "beverages"),
+    ("soda", "beverages"),
+    ("rice", "pantry"),
+    ("pasta", "pantry"),
+    ("noodle", "pantry"),
+    ("flour", "pantry"),
+    ("sugar", "pantry"),
+    ("salt", "pantry"),
+    ("oil", "pantry"),
+    ("vinegar", "pantry"),
+    ("cereal", "pantry"),
+    ("bean", "pantry"),
+    ("sauce", "pantry"),
+    ("chips", "snacks"),
+    ("crisps", "snacks"),
+    ("chocolate", "snacks"),
+    ("cookie", "snacks"),
+    ("biscuit", "snacks"),
+    ("nut", "snacks"),
+    ("soap", "household"),
+    ("detergent", "household"),
+    ("sponge", "household"),
+    ("foil", "household"),
+    ("toilet", "household"),
+    ("tissue", "household"),
+    ("shampoo", "personal care"),
+    ("toothpaste", "personal care"),
+    ("deodorant", "personal care"),
+];
+
+/// Guesses a category from an item's name, for `POST /items?auto_category=true`.
+///
+/// Words are looked up from the last one, so "apple juice" is a drink
+/// rather than fruit. Plural forms ("apples", "tomatoes") match their
+/// singular keyword.
+#[derive(Debug)]
+pub struct Categorizer {
+    keywords: HashMap<String, String>,
+}
+
+impl Categorizer {
+    pub fn built_in() -> Self {
+        Categorizer {
+            keywords: BUILT_IN
+                .iter()
+                .map(|(keyword, category)| (keyword.to_string(), category.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Adds `keyword = category` lines from `path` on top of the built-in
+    /// keywords, replacing any it repeats. Blank lines and lines starting
+    /// with `#` are skipped.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut categorizer = Self::built_in();
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line
+                .split_once('=')
+                .map(|(keyword, category)| (keyword.trim(), category.trim()))
+                .filter(|(keyword, category)| !keyword.is_empty() && !category.is_empty());
+            let Some((keyword, category)) = parsed else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected 'keyword = category'", number + 1),
+                ));
+            };
+            categorizer
+                .keywords
+                .insert(keyword.to_lowercase(), category.to_string());
+        }
+        Ok(categorizer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keywords.len()
+    }
+
+    /// The category of the last word of `name` with a keyword, if any.
+    pub fn category(&self, name: &str) -> Option<&str> {
+        name.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .rev()
+            .find_map(|word| {
+                let word = word.to_lowercase();
+                [
+                    Some(word.as_str()),
+                    word.strip_suffix('s'),
+                    word.strip_suffix("es"),
+                ]
+                .into_iter()
+                .flatten()
+                .find_map(|word| self.keywords.get(word))
+            })
+            .map(String::as_str)
+    }
+}
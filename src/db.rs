@@ -0,0 +1,784 @@
+use crate::logging;
+use log::{info, warn};
+use r2d2::event::{CheckoutEvent, HandleEvent, TimeoutEvent};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::trace::{TraceEvent, TraceEventCodes};
+use rusqlite::{Connection, ErrorCode};
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Schema changes applied on top of the original `shopping_items` table, in
+/// order. `PRAGMA user_version` records how many of them have already run.
+const MIGRATIONS: &[&str] = &[
+    // 1: prices and purchase history
+    "ALTER TABLE shopping_items ADD COLUMN price_cents INTEGER;
+     ALTER TABLE shopping_items ADD COLUMN paid_price_cents INTEGER;
+     ALTER TABLE shopping_items ADD COLUMN shopped_at TEXT;
+     CREATE TABLE purchase_history (
+         id INTEGER PRIMARY KEY,
+         item_id INTEGER NOT NULL,
+         name TEXT NOT NULL,
+         price_cents INTEGER,
+         purchased_at TEXT NOT NULL
+     );",
+    // 2: categories
+    "ALTER TABLE shopping_items ADD COLUMN category TEXT;",
+    // 3: quantities
+    "ALTER TABLE shopping_items ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1;",
+    // 4: last-modified timestamps, kept up to date by triggers so every write
+    // path is covered
+    "ALTER TABLE shopping_items ADD COLUMN updated_at TEXT;
+     UPDATE shopping_items SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+     CREATE TRIGGER shopping_items_inserted AFTER INSERT ON shopping_items
+     WHEN NEW.updated_at IS NULL
+     BEGIN
+         UPDATE shopping_items SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         WHERE id = NEW.id;
+     END;
+     CREATE TRIGGER shopping_items_updated AFTER UPDATE ON shopping_items
+     WHEN NEW.updated_at IS OLD.updated_at
+     BEGIN
+         UPDATE shopping_items SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         WHERE id = NEW.id;
+     END;",
+    // 5: pinned items
+    "ALTER TABLE shopping_items ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT 0;",
+    // 6: product images
+    "ALTER TABLE shopping_items ADD COLUMN image_url TEXT;",
+    // 7: reminders
+    "ALTER TABLE shopping_items ADD COLUMN remind_at TEXT;",
+    // 8: units, and enough detail in the history to re-add a purchase
+    "ALTER TABLE shopping_items ADD COLUMN unit TEXT;
+     ALTER TABLE purchase_history ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1;
+     ALTER TABLE purchase_history ADD COLUMN category TEXT;
+     ALTER TABLE purchase_history ADD COLUMN unit TEXT;",
+    // 9: fractional amounts, in thousandths of the unit
+    "ALTER TABLE shopping_items ADD COLUMN amount_milli INTEGER;
+     ALTER TABLE purchase_history ADD COLUMN amount_milli INTEGER;",
+    // 10: multiple lists; existing items end up on the first one
+    "CREATE TABLE lists (
+         id INTEGER PRIMARY KEY,
+         name TEXT NOT NULL
+     );
+     INSERT INTO lists (id, name) VALUES (1, 'Shopping list');
+     ALTER TABLE shopping_items ADD COLUMN list_id INTEGER NOT NULL DEFAULT 1;
+     CREATE INDEX shopping_items_list_id ON shopping_items (list_id);",
+    // 11: indexes for name lookups and category filters
+    "CREATE INDEX IF NOT EXISTS idx_items_name ON shopping_items (name COLLATE NOCASE);
+     CREATE INDEX IF NOT EXISTS idx_history_name ON purchase_history (name COLLATE NOCASE);
+     CREATE INDEX IF NOT EXISTS idx_items_category ON shopping_items (category);",
+    // 12: manual ordering; new items go to the bottom of their list
+    "ALTER TABLE shopping_items ADD COLUMN position INTEGER;
+     UPDATE shopping_items SET position = id;
+     CREATE TRIGGER shopping_items_positioned AFTER INSERT ON shopping_items
+     WHEN NEW.position IS NULL
+     BEGIN
+         UPDATE shopping_items
+         SET position = (SELECT COALESCE(MAX(position), 0) + 1 FROM shopping_items
+                         WHERE list_id = NEW.list_id)
+         WHERE id = NEW.id;
+     END;
+     CREATE INDEX idx_items_position ON shopping_items (list_id, position);",
+    // 13: fractional positions, so an item can move between two others by
+    // taking the midpoint. The table is rebuilt to give the column REAL
+    // affinity, since DROP COLUMN needs SQLite 3.35; dropping the old table
+    // drops its indexes and triggers, so they are recreated as well.
+    "CREATE TABLE shopping_items_new (
+         id INTEGER PRIMARY KEY,
+         name TEXT NOT NULL,
+         is_shopped BOOLEAN NOT NULL,
+         price_cents INTEGER,
+         paid_price_cents INTEGER,
+         shopped_at TEXT,
+         category TEXT,
+         quantity INTEGER NOT NULL DEFAULT 1,
+         updated_at TEXT,
+         pinned BOOLEAN NOT NULL DEFAULT 0,
+         image_url TEXT,
+         remind_at TEXT,
+         unit TEXT,
+         amount_milli INTEGER,
+         list_id INTEGER NOT NULL DEFAULT 1,
+         position REAL
+     );
+     INSERT INTO shopping_items_new
+         (id, name, is_shopped, price_cents, paid_price_cents, shopped_at, category,
+          quantity, updated_at, pinned, image_url, remind_at, unit, amount_milli,
+          list_id, position)
+     SELECT id, name, is_shopped, price_cents, paid_price_cents, shopped_at, category,
+            quantity, updated_at, pinned, image_url, remind_at, unit, amount_milli,
+            list_id, position
+     FROM shopping_items;
+     DROP TABLE shopping_items;
+     ALTER TABLE shopping_items_new RENAME TO shopping_items;
+     CREATE INDEX shopping_items_list_id ON shopping_items (list_id);
+     CREATE INDEX idx_items_name ON shopping_items (name COLLATE NOCASE);
+     CREATE INDEX idx_items_category ON shopping_items (category);
+     CREATE INDEX idx_items_position ON shopping_items (list_id, position);
+     CREATE TRIGGER shopping_items_inserted AFTER INSERT ON shopping_items
+     WHEN NEW.updated_at IS NULL
+     BEGIN
+         UPDATE shopping_items SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         WHERE id = NEW.id;
+     END;
+     CREATE TRIGGER shopping_items_updated AFTER UPDATE ON shopping_items
+     WHEN NEW.updated_at IS OLD.updated_at
+     BEGIN
+         UPDATE shopping_items SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         WHERE id = NEW.id;
+     END;
+     CREATE TRIGGER shopping_items_positioned AFTER INSERT ON shopping_items
+     WHEN NEW.position IS NULL
+     BEGIN
+         UPDATE shopping_items
+         SET position = (SELECT COALESCE(MAX(position), 0) + 1 FROM shopping_items
+                         WHERE list_id = NEW.list_id)
+         WHERE id = NEW.id;
+     END;",
+    // 14: settings, a single row
+    "CREATE TABLE settings (
+         id INTEGER PRIMARY KEY CHECK (id = 1),
+         default_quantity INTEGER NOT NULL DEFAULT 1,
+         default_category TEXT
+     );
+     INSERT INTO settings (id) VALUES (1);",
+    // 15: store layout order within a category
+    "ALTER TABLE shopping_items ADD COLUMN aisle_position INTEGER;",
+    // 16: creation times; the last change is the best guess for old items
+    "ALTER TABLE shopping_items ADD COLUMN created_at TEXT;
+     UPDATE shopping_items SET created_at = updated_at;
+     CREATE TRIGGER shopping_items_created AFTER INSERT ON shopping_items
+     WHEN NEW.created_at IS NULL
+     BEGIN
+         UPDATE shopping_items SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         WHERE id = NEW.id;
+     END;",
+    // 17: activity feed. Updates that only touch updated_at, created_at or
+    // position are bookkeeping (mostly by the triggers above) and skipped.
+    "CREATE TABLE audit_log (
+         id INTEGER PRIMARY KEY,
+         op TEXT NOT NULL,
+         item_id INTEGER NOT NULL,
+         at TEXT NOT NULL,
+         name_before TEXT,
+         shopped_before BOOLEAN,
+         name_after TEXT,
+         shopped_after BOOLEAN
+     );
+     CREATE INDEX idx_audit_log_at ON audit_log (at);
+     CREATE TRIGGER audit_items_inserted AFTER INSERT ON shopping_items
+     BEGIN
+         INSERT INTO audit_log (op, item_id, at, name_after, shopped_after)
+         VALUES ('add', NEW.id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                 NEW.name, NEW.is_shopped);
+     END;
+     CREATE TRIGGER audit_items_updated AFTER UPDATE ON shopping_items
+     WHEN OLD.name IS NOT NEW.name
+       OR OLD.is_shopped IS NOT NEW.is_shopped
+       OR OLD.quantity IS NOT NEW.quantity
+       OR OLD.category IS NOT NEW.category
+       OR OLD.pinned IS NOT NEW.pinned
+       OR OLD.price_cents IS NOT NEW.price_cents
+       OR OLD.paid_price_cents IS NOT NEW.paid_price_cents
+       OR OLD.unit IS NOT NEW.unit
+       OR OLD.amount_milli IS NOT NEW.amount_milli
+       OR OLD.list_id IS NOT NEW.list_id
+       OR OLD.image_url IS NOT NEW.image_url
+       OR OLD.remind_at IS NOT NEW.remind_at
+       OR OLD.aisle_position IS NOT NEW.aisle_position
+     BEGIN
+         INSERT INTO audit_log
+             (op, item_id, at, name_before, shopped_before, name_after, shopped_after)
+         VALUES ('update', NEW.id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                 OLD.name, OLD.is_shopped, NEW.name, NEW.is_shopped);
+     END;
+     CREATE TRIGGER audit_items_deleted AFTER DELETE ON shopping_items
+     BEGIN
+         INSERT INTO audit_log (op, item_id, at, name_before, shopped_before)
+         VALUES ('delete', OLD.id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                 OLD.name, OLD.is_shopped);
+     END;",
+    // 18: list colors and icons
+    "ALTER TABLE lists ADD COLUMN color TEXT;
+     ALTER TABLE lists ADD COLUMN icon TEXT;",
+    // 19: per-item history
+    "CREATE INDEX idx_audit_log_item_id ON audit_log (item_id);",
+    // 20: how much of an item was actually bought. Shopping an item without
+    // saying defaults it to the planned quantity; unshopping clears it.
+    "ALTER TABLE shopping_items ADD COLUMN quantity_bought INTEGER;
+     UPDATE shopping_items SET quantity_bought = quantity WHERE is_shopped;
+     CREATE TRIGGER shopping_items_bought_inserted AFTER INSERT ON shopping_items
+     WHEN NEW.is_shopped AND NEW.quantity_bought IS NULL
+     BEGIN
+         UPDATE shopping_items SET quantity_bought = NEW.quantity WHERE id = NEW.id;
+     END;
+     CREATE TRIGGER shopping_items_bought AFTER UPDATE OF is_shopped ON shopping_items
+     WHEN NEW.is_shopped IS NOT OLD.is_shopped
+     BEGIN
+         UPDATE shopping_items
+         SET quantity_bought = CASE WHEN NEW.is_shopped
+                                    THEN COALESCE(NEW.quantity_bought, NEW.quantity) END
+         WHERE id = NEW.id;
+     END;",
+    // 21: sub-items. Children of an item that is deleted or moved to another
+    // list become top-level items.
+    "ALTER TABLE shopping_items ADD COLUMN parent_id INTEGER;
+     CREATE INDEX idx_items_parent_id ON shopping_items (parent_id);
+     CREATE TRIGGER shopping_items_orphaned AFTER DELETE ON shopping_items
+     BEGIN
+         UPDATE shopping_items SET parent_id = NULL WHERE parent_id = OLD.id;
+     END;
+     CREATE TRIGGER shopping_items_children_left AFTER UPDATE OF list_id ON shopping_items
+     WHEN NEW.list_id IS NOT OLD.list_id
+     BEGIN
+         UPDATE shopping_items SET parent_id = NULL WHERE parent_id = NEW.id;
+     END;",
+    // 22: archived shopping trips
+    "CREATE TABLE trips (
+         id INTEGER PRIMARY KEY,
+         completed_at TEXT NOT NULL,
+         list_id INTEGER,
+         total_cents INTEGER
+     );
+     CREATE TABLE trip_items (
+         id INTEGER PRIMARY KEY,
+         trip_id INTEGER NOT NULL REFERENCES trips (id),
+         item_id INTEGER NOT NULL,
+         name TEXT NOT NULL,
+         quantity INTEGER NOT NULL,
+         quantity_bought INTEGER,
+         category TEXT,
+         unit TEXT,
+         amount_milli INTEGER,
+         price_cents INTEGER
+     );
+     CREATE INDEX idx_trip_items_trip_id ON trip_items (trip_id);",
+    // 23: snoozed items
+    "ALTER TABLE shopping_items ADD COLUMN snoozed_until TEXT;",
+    // 24: barcodes for scan-to-add
+    "ALTER TABLE shopping_items ADD COLUMN barcode TEXT;
+     CREATE UNIQUE INDEX idx_items_barcode ON shopping_items (barcode)
+         WHERE barcode IS NOT NULL;",
+    // 25: store layouts for ?route=
+    "CREATE TABLE store_layouts (
+         store TEXT NOT NULL,
+         category TEXT NOT NULL,
+         sequence INTEGER NOT NULL,
+         PRIMARY KEY (store, category)
+     );",
+];
+
+/// Indexes the queries rely on, checked by [`check_indexes`] at startup.
+const EXPECTED_INDEXES: &[&str] = &[
+    "shopping_items_list_id",
+    "idx_items_name",
+    "idx_history_name",
+    "idx_items_category",
+    "idx_items_position",
+    "idx_audit_log_at",
+    "idx_audit_log_item_id",
+    "idx_items_parent_id",
+    "idx_trip_items_trip_id",
+    "idx_items_barcode",
+];
+
+/// Collation used when ordering by item name: case-insensitive and treating
+/// accented letters like their base letter, so "Ärger" sorts next to "apfel"
+/// rather than after "Zebra".
+pub const NAME_COLLATION: &str = "NAME_NOCASE";
+
+/// Whether names differing only in case, like "Milk" and "milk", count as
+/// the same item when suggesting or merging names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameMatching {
+    CaseSensitive,
+    CaseInsensitive,
+}
+
+impl NameMatching {
+    /// Collation comparing names under this policy. `NOCASE` only folds
+    /// ASCII letters.
+    pub fn collation(self) -> &'static str {
+        match self {
+            NameMatching::CaseSensitive => "BINARY",
+            NameMatching::CaseInsensitive => "NOCASE",
+        }
+    }
+}
+
+/// Statements running at least this long are logged, see [`open`].
+static SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(100);
+
+pub fn set_slow_query_threshold(threshold: Duration) {
+    SLOW_QUERY_MS.store(threshold.as_millis() as u64, AtomicOrdering::Relaxed);
+}
+
+/// Number of statements run for one request, kept in its extensions by
+/// the `?debug=true` envelope.
+#[derive(Debug, Clone, Default)]
+pub struct QueryCounter(Rc<Cell<u64>>);
+
+impl QueryCounter {
+    pub fn get(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+thread_local! {
+    /// Counter of the request being polled on this thread, if it is counted.
+    static CURRENT_COUNTER: RefCell<Option<QueryCounter>> = const { RefCell::new(None) };
+}
+
+/// Future returned by [`count_queries`].
+pub struct CountQueries<F> {
+    counter: QueryCounter,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for CountQueries<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // Statements run synchronously during a poll, so every one executed
+        // while this counter is current belongs to this request.
+        let counter = self.counter.clone();
+        let previous = CURRENT_COUNTER.with(|current| current.replace(Some(counter)));
+        let result = self.inner.as_mut().poll(cx);
+        CURRENT_COUNTER.with(|current| *current.borrow_mut() = previous);
+        result
+    }
+}
+
+/// Counts the statements `future` runs on this thread in `counter`.
+/// Work moved to other threads, like streamed responses, isn't counted.
+pub fn count_queries<F: Future>(counter: &QueryCounter, future: F) -> CountQueries<F> {
+    CountQueries {
+        counter: counter.clone(),
+        inner: Box::pin(future),
+    }
+}
+
+thread_local! {
+    /// Deadline of the request being polled on this thread, if it has one.
+    static CURRENT_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Virtual machine steps between two deadline checks of a running
+/// statement.
+const DEADLINE_CHECK_OPS: i32 = 1000;
+
+/// Future returned by [`with_deadline`].
+pub struct WithDeadline<F> {
+    deadline: Instant,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithDeadline<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let previous = CURRENT_DEADLINE.with(|current| current.replace(Some(self.deadline)));
+        let result = self.inner.as_mut().poll(cx);
+        CURRENT_DEADLINE.with(|current| current.set(previous));
+        result
+    }
+}
+
+/// Interrupts statements `future` runs on this thread once `deadline` has
+/// passed. They fail with an error [`is_interrupted`] recognizes. A timer
+/// alone can't do this, since a running statement blocks the thread the
+/// timer would fire on.
+pub fn with_deadline<F: Future>(deadline: Instant, future: F) -> WithDeadline<F> {
+    WithDeadline {
+        deadline,
+        inner: Box::pin(future),
+    }
+}
+
+/// Progress handler of every connection: a `true` interrupts the running
+/// statement.
+fn past_deadline() -> bool {
+    CURRENT_DEADLINE.with(|current| {
+        current
+            .get()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    })
+}
+
+/// Whether a statement failed because its request ran out of time, see
+/// [`with_deadline`].
+pub fn is_interrupted(e: &rusqlite::Error) -> bool {
+    e.sqlite_error_code() == Some(ErrorCode::OperationInterrupted)
+}
+
+/// Called when a statement finishes: counts it for the current request and
+/// logs it if it was slow.
+fn profile_statement(event: TraceEvent<'_>) {
+    CURRENT_COUNTER.with(|current| {
+        if let Some(counter) = &*current.borrow() {
+            counter.0.set(counter.0.get() + 1);
+        }
+    });
+
+    if let TraceEvent::Profile(stmt, elapsed) = event
+        && elapsed.as_millis() as u64 >= SLOW_QUERY_MS.load(AtomicOrdering::Relaxed)
+    {
+        let sql = stmt.sql();
+        warn!(
+            target: logging::DB,
+            "Slow query took {}ms: {}",
+            elapsed.as_millis(),
+            sql.split_whitespace().collect::<Vec<_>>().join(" ")
+        );
+    }
+}
+
+/// Opens a connection with the crate's custom collations registered and
+/// slow statements logged.
+pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    configure(&conn)?;
+    Ok(conn)
+}
+
+/// Registers the collations, the statement profiler and the deadline check
+/// on a new connection.
+fn configure(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_collation(NAME_COLLATION, compare_names)?;
+    conn.trace_v2(
+        TraceEventCodes::SQLITE_TRACE_PROFILE,
+        Some(profile_statement),
+    );
+    conn.progress_handler(DEADLINE_CHECK_OPS, Some(past_deadline));
+    Ok(())
+}
+
+fn compare_names(a: &str, b: &str) -> Ordering {
+    name_sort_key(a)
+        .cmp(&name_sort_key(b))
+        .then_with(|| a.cmp(b))
+}
+
+/// Lowercases `name` and strips common Latin diacritics (DIN 5007 style,
+/// e.g. "ä" → "a", "ß" → "ss").
+fn name_sort_key(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    for c in name.chars().flat_map(char::to_lowercase) {
+        match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => key.push('a'),
+            'æ' => key.push_str("ae"),
+            'ç' | 'ć' | 'č' => key.push('c'),
+            'ď' | 'đ' => key.push('d'),
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => key.push('e'),
+            'ì' | 'í' | 'î' | 'ï' | 'ī' | 'į' | 'ı' => key.push('i'),
+            'ł' => key.push('l'),
+            'ñ' | 'ń' | 'ň' => key.push('n'),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => key.push('o'),
+            'œ' => key.push_str("oe"),
+            'ř' => key.push('r'),
+            'ś' | 'š' | 'ş' => key.push('s'),
+            'ß' => key.push_str("ss"),
+            'ť' | 'ţ' => key.push('t'),
+            'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' | 'ų' => key.push('u'),
+            'ý' | 'ÿ' => key.push('y'),
+            'ź' | 'ż' | 'ž' => key.push('z'),
+            c => key.push(c),
+        }
+    }
+    key
+}
+
+/// Creates the schema if needed and brings it up to the latest version.
+pub fn init(conn: &mut Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS shopping_items (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            is_shopped BOOLEAN NOT NULL
+        )",
+        [],
+    )?;
+
+    let version = schema_version(conn)?;
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", idx + 1)?;
+        tx.commit()?;
+        info!(
+            target: logging::DB,
+            "Applied database migration {}",
+            idx + 1
+        );
+    }
+    Ok(())
+}
+
+/// Logs which of the expected indexes exist, warning about missing ones
+/// since queries fall back to full table scans without them.
+pub fn check_indexes(conn: &Connection) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?1)",
+    )?;
+    for index in EXPECTED_INDEXES {
+        if stmt.query_row([index], |row| row.get(0))? {
+            info!(target: logging::DB, "Found index {}", index);
+        } else {
+            warn!(
+                target: logging::DB,
+                "Missing index {}, queries using it will scan the table", index
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Number of migrations applied to the database.
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Error from [`Pool::get`]: no connection became available within the
+/// configured timeout, including because new ones failed to open.
+pub type PoolError = r2d2::Error;
+
+/// A connection checked out of a [`Pool`], returned to it on drop.
+pub type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+/// Checkout counters kept by [`Stats`] for [`Pool::state`].
+#[derive(Debug, Default)]
+struct Counters {
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Stats(Arc<Counters>);
+
+impl HandleEvent for Stats {
+    fn handle_checkout(&self, _: CheckoutEvent) {
+        self.0.checkouts.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+
+    fn handle_timeout(&self, _: TimeoutEvent) {
+        self.0.timeouts.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+}
+
+/// Snapshot of a [`Pool`], see [`Pool::state`].
+#[derive(Debug, Clone, Copy)]
+pub struct State {
+    pub max_size: u32,
+    /// Connections currently open, idle or in use.
+    pub connections: u32,
+    pub idle_connections: u32,
+    /// Successful [`Pool::get`] calls since startup.
+    pub checkouts: u64,
+    /// [`Pool::get`] calls that gave up waiting since startup.
+    pub timeouts: u64,
+}
+
+/// An r2d2 pool of SQLite connections set up like [`open`], opened lazily
+/// up to `max_size`.
+#[derive(Clone)]
+pub struct Pool {
+    inner: r2d2::Pool<SqliteConnectionManager>,
+    counters: Arc<Counters>,
+}
+
+pub struct Builder {
+    max_size: u32,
+    connection_timeout: Duration,
+}
+
+impl Builder {
+    pub fn max_size(mut self, max_size: u32) -> Self {
+        assert!(max_size > 0, "pool max_size must be positive");
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn connection_timeout(mut self, connection_timeout: Duration) -> Self {
+        self.connection_timeout = connection_timeout;
+        self
+    }
+
+    pub fn build(self, path: impl AsRef<Path>) -> Pool {
+        let busy_timeout = self.connection_timeout;
+        let manager = SqliteConnectionManager::file(path).with_init(move |conn| {
+            configure(conn)?;
+            conn.busy_timeout(busy_timeout)
+        });
+        let counters = Arc::new(Counters::default());
+        let inner = r2d2::Pool::builder()
+            .max_size(self.max_size)
+            .min_idle(Some(0))
+            .connection_timeout(self.connection_timeout)
+            .event_handler(Box::new(Stats(Arc::clone(&counters))))
+            .build_unchecked(manager);
+        Pool { inner, counters }
+    }
+}
+
+impl Pool {
+    pub fn builder() -> Builder {
+        Builder {
+            max_size: 10,
+            connection_timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Checks out a connection, opening a new one if the pool is not yet full
+    /// and otherwise waiting up to the connection timeout for one to be
+    /// returned.
+    pub fn get(&self) -> Result<PooledConnection, PoolError> {
+        self.inner.get()
+    }
+
+    /// Current pool usage, cheap enough to poll.
+    pub fn state(&self) -> State {
+        let state = self.inner.state();
+        State {
+            max_size: self.inner.max_size(),
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+            checkouts: self.counters.checkouts.load(AtomicOrdering::Relaxed),
+            timeouts: self.counters.timeouts.load(AtomicOrdering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_applies_every_migration() {
+        let mut conn = open(":memory:").unwrap();
+        init(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+
+        let missing: Vec<_> = EXPECTED_INDEXES
+            .iter()
+            .filter(|index| {
+                !conn
+                    .query_row(
+                        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?1)",
+                        [index],
+                        |row| row.get::<_, bool>(0),
+                    )
+                    .unwrap()
+            })
+            .collect();
+        assert!(missing.is_empty(), "missing indexes: {:?}", missing);
+    }
+
+    #[test]
+    fn fractional_positions_keep_items_and_triggers() {
+        let mut conn = open(":memory:").unwrap();
+        conn.execute_batch(
+            "CREATE TABLE shopping_items (
+                 id INTEGER PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 is_shopped BOOLEAN NOT NULL
+             );",
+        )
+        .unwrap();
+        for migration in &MIGRATIONS[..12] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 12).unwrap();
+        conn.execute(
+            "INSERT INTO shopping_items (name, is_shopped, quantity, list_id) VALUES ('milk', 0, 2, 1)",
+            [],
+        )
+        .unwrap();
+
+        init(&mut conn).unwrap();
+
+        let (name, quantity, position): (String, i64, f64) = conn
+            .query_row(
+                "SELECT name, quantity, position FROM shopping_items WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((name.as_str(), quantity, position), ("milk", 2, 1.0));
+
+        conn.execute(
+            "INSERT INTO shopping_items (name, is_shopped) VALUES ('eggs', 0)",
+            [],
+        )
+        .unwrap();
+        conn.execute("UPDATE shopping_items SET position = 1.5 WHERE id = 2", [])
+            .unwrap();
+        let (kind, updated_at): (String, Option<String>) = conn
+            .query_row(
+                "SELECT typeof(position), updated_at FROM shopping_items WHERE id = 2",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(kind, "real");
+        assert!(updated_at.is_some());
+    }
+
+    #[test]
+    fn pool_times_out_when_exhausted() {
+        let pool = Pool::builder()
+            .max_size(1)
+            .connection_timeout(Duration::from_millis(50))
+            .build(":memory:");
+
+        let conn = pool.get().unwrap();
+        // Pooled connections are set up like `open`'s.
+        conn.query_row("SELECT 'a' < 'B' COLLATE NAME_NOCASE", [], |row| {
+            row.get::<_, bool>(0)
+        })
+        .unwrap();
+        assert!(pool.get().is_err());
+
+        let state = pool.state();
+        assert_eq!((state.max_size, state.connections), (1, 1));
+        assert_eq!((state.checkouts, state.timeouts), (1, 1));
+
+        drop(conn);
+        assert!(pool.get().is_ok());
+    }
+
+    #[test]
+    fn name_collation_ignores_case_and_accents() {
+        let conn = open(":memory:").unwrap();
+        conn.execute_batch(
+            "CREATE TABLE names (name TEXT);
+             INSERT INTO names VALUES ('Zebra'), ('Ärger'), ('apfel'), ('Birne'), ('äpfel'),
+                                      ('Apfel'), ('Straße'), ('strasse'), ('Öl'), ('oliven');",
+        )
+        .unwrap();
+
+        let sorted: Vec<String> = conn
+            .prepare("SELECT name FROM names ORDER BY name COLLATE NAME_NOCASE")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        // Names equal apart from case and accents fall back to comparing
+        // bytes, so the order is still total.
+        assert_eq!(
+            sorted,
+            [
+                "Apfel", "apfel", "äpfel", "Ärger", "Birne", "Öl", "oliven", "Straße", "strasse",
+                "Zebra"
+            ]
+        );
+    }
+}
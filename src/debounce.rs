@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Drops repeated toggles of the same item by the same client within
+/// `window`, set by `TOGGLE_DEBOUNCE_MS`, so a double tap on a laggy UI
+/// doesn't flip an item straight back.
+#[derive(Debug)]
+pub struct Debouncer {
+    window: Duration,
+    last_toggled: Mutex<HashMap<(i32, String), Instant>>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Debouncer {
+            window,
+            last_toggled: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Locks the toggle times. A panic elsewhere while holding the lock can
+    /// at worst leave a stale entry behind, so poisoning is ignored rather
+    /// than failing every later toggle.
+    fn lock(&self) -> MutexGuard<'_, HashMap<(i32, String), Instant>> {
+        self.last_toggled
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records a toggle and returns `true`, or returns `false` if the client
+    /// already toggled the item within the window. Ignored toggles don't
+    /// extend the window, so steady tapping still gets through.
+    pub fn allow(&self, item_id: i32, client: &str) -> bool {
+        let now = Instant::now();
+        let mut last_toggled = self.lock();
+        match last_toggled.get(&(item_id, client.to_string())) {
+            Some(&at) if now.duration_since(at) < self.window => false,
+            _ => {
+                last_toggled.insert((item_id, client.to_string()), now);
+                true
+            }
+        }
+    }
+
+    /// Forgets toggles older than the window, returning how many.
+    pub fn prune(&self) -> usize {
+        let now = Instant::now();
+        let mut last_toggled = self.lock();
+        let before = last_toggled.len();
+        last_toggled.retain(|_, &mut at| now.duration_since(at) < self.window);
+        before - last_toggled.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    #[test]
+    fn repeated_toggles_are_dropped_within_the_window() {
+        let debouncer = Debouncer::new(Duration::from_secs(60));
+        assert!(debouncer.allow(1, "a"));
+        assert!(!debouncer.allow(1, "a"));
+        assert!(debouncer.allow(1, "b"));
+        assert!(debouncer.allow(2, "a"));
+    }
+
+    #[test]
+    fn keeps_working_after_a_panic_while_locked() {
+        let debouncer = Debouncer::new(Duration::from_secs(60));
+        assert!(debouncer.allow(1, "a"));
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let _guard = debouncer.last_toggled.lock().unwrap();
+            panic!("poison the lock");
+        }));
+        assert!(result.is_err());
+        assert!(debouncer.last_toggled.is_poisoned());
+
+        assert!(!debouncer.allow(1, "a"));
+        assert!(debouncer.allow(2, "a"));
+        assert_eq!(debouncer.prune(), 0);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::db;
+use actix_web::body::{self, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, web};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::time::Instant;
+
+#[derive(Debug, Deserialize)]
+struct DebugQuery {
+    debug: Option<String>,
+}
+
+fn wants_debug(req: &ServiceRequest) -> bool {
+    web::Query::<DebugQuery>::from_query(req.query_string())
+        .is_ok_and(|query| query.debug.as_deref().is_some_and(crate::pretty::is_true))
+}
+
+/// With `?debug=true`, wraps JSON responses as
+/// `{ "data": ..., "server_time_ms": ..., "db_queries": ... }` so frontend
+/// developers can see what a request cost on the server. The time runs
+/// until the handler has produced its response.
+pub async fn middleware<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B, BoxBody>>, Error> {
+    if !wants_debug(&req) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    // The query budget middleware is already counting this request's
+    // statements; counting them again here would hide them from it.
+    let counter = req.extensions().get::<db::QueryCounter>().cloned();
+    let started = Instant::now();
+    let (counter, res) = match counter {
+        Some(counter) => (counter, next.call(req).await?),
+        None => {
+            let counter = db::QueryCounter::default();
+            req.extensions_mut().insert(counter.clone());
+            (
+                counter.clone(),
+                db::count_queries(&counter, next.call(req)).await?,
+            )
+        }
+    };
+    let server_time_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return Ok(res.map_into_left_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = body::to_bytes(body).await.map_err(Into::into)?;
+    // An empty body becomes `"data": null`; one that isn't JSON after all
+    // is passed on as it is.
+    let data = if bytes.is_empty() {
+        Value::Null
+    } else {
+        match serde_json::from_slice(&bytes) {
+            Ok(data) => data,
+            Err(_) => {
+                let res = res.set_body(BoxBody::new(bytes));
+                return Ok(ServiceResponse::new(req, res).map_into_right_body());
+            }
+        }
+    };
+    let envelope = json!({
+        "data": data,
+        "server_time_ms": (server_time_ms * 1000.0).round() / 1000.0,
+        "db_queries": counter.get(),
+    });
+    let mut res = res.set_body(BoxBody::new(serde_json::to_vec(&envelope)?));
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
@@ -0,0 +1,117 @@
+use crate::db;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use rusqlite::{Connection, Transaction};
+use serde_json::json;
+use std::fmt;
+use std::time::Duration;
+
+/// Error returned by handlers, rendered as `{ "error": "..." }`.
+///
+/// Server-side failures are logged with the request id by
+/// [`crate::request_id::middleware`], so handlers can simply propagate them
+/// with `?`.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    /// Missing or wrong API key.
+    Unauthorized,
+    /// The endpoint is disabled by configuration.
+    Forbidden(String),
+    UnsupportedMediaType(String),
+    PayloadTooLarge(String),
+    /// Clashes with the current state, such as a barcode already in use.
+    Conflict(String),
+    /// Well-formed but refused, such as a name caught by the word filter.
+    Unprocessable(String),
+    /// No database connection became available in time.
+    Unavailable(db::PoolError),
+    /// The request took longer than `REQUEST_TIMEOUT_MS`.
+    Timeout(Duration),
+    Database(rusqlite::Error),
+    /// Any other server-side failure, described for the log only.
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn item_not_found(id: i32) -> Self {
+        ApiError::NotFound(format!("Item {} not found", id))
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::NotFound(message)
+            | ApiError::Forbidden(message)
+            | ApiError::UnsupportedMediaType(message)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::Conflict(message)
+            | ApiError::Unprocessable(message) => f.write_str(message),
+            ApiError::Unauthorized => f.write_str("Missing or invalid API key"),
+            ApiError::Unavailable(e) => write!(f, "database pool exhausted: {}", e),
+            ApiError::Timeout(limit) => write!(
+                f,
+                "Request did not complete within {}ms, please retry",
+                limit.as_millis()
+            ),
+            ApiError::Database(e) => write!(f, "database error: {}", e),
+            ApiError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unavailable(_) | ApiError::Timeout(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            // Don't leak database details to clients.
+            ApiError::Database(_) | ApiError::Internal(_) => "Internal server error".to_string(),
+            ApiError::Unavailable(_) => "Database is busy, please retry".to_string(),
+            _ => self.to_string(),
+        };
+        HttpResponse::build(self.status_code()).json(json!({ "error": message }))
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> Self {
+        ApiError::Database(e)
+    }
+}
+
+impl From<db::PoolError> for ApiError {
+    fn from(e: db::PoolError) -> Self {
+        ApiError::Unavailable(e)
+    }
+}
+
+/// Runs `f` inside a transaction, committing if it returns `Ok` and rolling
+/// back otherwise, so `f` can reject the request midway with any
+/// [`ApiError`].
+pub fn with_transaction<T>(
+    conn: &mut Connection,
+    f: impl FnOnce(&Transaction) -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let tx = conn.transaction()?;
+    // Dropping an uncommitted transaction rolls it back.
+    let value = f(&tx)?;
+    tx.commit()?;
+    Ok(value)
+}
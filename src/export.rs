@@ -0,0 +1,95 @@
+use crate::{ShoppingItem, quantity};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Version of the `GET /items/export.json` format. Bump it, and teach
+/// `import::parse_json_export` to upgrade the old version, whenever a change
+/// would stop an older file from importing as it is. Version 1 is the bare
+/// item array `GET /items` returns, which was the only export before this
+/// format existed.
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// The whole list as JSON, for `POST /items/import.json` to restore.
+#[derive(Debug, Serialize)]
+pub struct JsonExport<'a> {
+    pub schema_version: u64,
+    pub exported_at: String,
+    pub items: &'a [ShoppingItem],
+}
+
+pub fn json(items: &[ShoppingItem], now: time::OffsetDateTime) -> JsonExport<'_> {
+    JsonExport {
+        schema_version: SCHEMA_VERSION,
+        exported_at: crate::timestamp::format(now),
+        items,
+    }
+}
+
+/// Renders the list as a Markdown checklist. When any item has a category,
+/// items are grouped under `## Category` headers, with uncategorized items
+/// last.
+pub fn markdown(items: &[ShoppingItem]) -> String {
+    let mut out = String::new();
+
+    if items.iter().all(|item| item.category.is_none()) {
+        for item in items {
+            push_markdown_line(&mut out, item);
+        }
+        return out;
+    }
+
+    let mut groups: BTreeMap<&str, Vec<&ShoppingItem>> = BTreeMap::new();
+    let mut uncategorized = Vec::new();
+    for item in items {
+        match item.category.as_deref() {
+            Some(category) => groups.entry(category).or_default().push(item),
+            None => uncategorized.push(item),
+        }
+    }
+
+    let uncategorized = (!uncategorized.is_empty()).then_some(("Uncategorized", uncategorized));
+    for (category, items) in groups.into_iter().chain(uncategorized) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str("## ");
+        out.push_str(&escape_markdown(category));
+        out.push('\n');
+        for item in items {
+            push_markdown_line(&mut out, item);
+        }
+    }
+    out
+}
+
+/// Renders the list as one `[ ] 2 L milk` / `[x] 3 apples` line per item,
+/// for terminal clients.
+pub fn plain_text(items: &[ShoppingItem]) -> String {
+    let mut out = String::new();
+    for item in items {
+        out.push_str(if item.is_shopped { "[x] " } else { "[ ] " });
+        out.push_str(&quantity::label(item).replace(['\n', '\r'], " "));
+        out.push('\n');
+    }
+    out
+}
+
+fn push_markdown_line(out: &mut String, item: &ShoppingItem) {
+    out.push_str(if item.is_shopped { "- [x] " } else { "- [ ] " });
+    out.push_str(&escape_markdown(&quantity::label(item)));
+    out.push('\n');
+}
+
+/// Backslash-escapes characters Markdown would otherwise interpret, so item
+/// names like `[sale]` or `*new*` render literally.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_{}[]()<>#+-.!|~".contains(c) {
+            escaped.push('\\');
+        }
+        // A newline would end the checklist entry.
+        escaped.push(if c == '\n' || c == '\r' { ' ' } else { c });
+    }
+    escaped
+}
@@ -0,0 +1,102 @@
+use crate::{ShoppingItem, quantity, timestamp};
+use time::macros::format_description;
+use time::{OffsetDateTime, UtcOffset};
+
+/// Longest content line in octets, excluding the CRLF (RFC 5545 3.1).
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Renders the list as an iCalendar document with one `VTODO` per item, so
+/// todo apps can subscribe to it. Shopped items are marked completed.
+pub fn todos(items: &[ShoppingItem], now: OffsetDateTime) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//shoppinglist-backend//EN");
+
+    let now = format_utc(now);
+    for item in items {
+        push_line(&mut out, "BEGIN:VTODO");
+        push_line(
+            &mut out,
+            &format!(
+                "UID:item-{}@shoppinglist-backend",
+                item.id.unwrap_or_default()
+            ),
+        );
+        let stamp = item.updated_at.as_deref().and_then(ical_time);
+        push_line(
+            &mut out,
+            &format!("DTSTAMP:{}", stamp.as_deref().unwrap_or(&now)),
+        );
+        push_line(
+            &mut out,
+            &format!("SUMMARY:{}", escape_text(&quantity::label(item))),
+        );
+        if let Some(category) = &item.category {
+            push_line(&mut out, &format!("CATEGORIES:{}", escape_text(category)));
+        }
+        if let Some(due) = item.remind_at.as_deref().and_then(ical_time) {
+            push_line(&mut out, &format!("DUE:{}", due));
+        }
+        if item.is_shopped {
+            push_line(&mut out, "STATUS:COMPLETED");
+            if let Some(completed) = item.shopped_at.as_deref().and_then(ical_time) {
+                push_line(&mut out, &format!("COMPLETED:{}", completed));
+            }
+        } else {
+            push_line(&mut out, "STATUS:NEEDS-ACTION");
+        }
+        push_line(&mut out, "END:VTODO");
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// Converts a stored RFC 3339 timestamp to an iCalendar UTC date-time.
+fn ical_time(value: &str) -> Option<String> {
+    timestamp::parse(value).map(format_utc)
+}
+
+fn format_utc(value: OffsetDateTime) -> String {
+    value
+        .to_offset(UtcOffset::UTC)
+        .format(format_description!(
+            "[year][month][day]T[hour][minute][second]Z"
+        ))
+        .expect("timestamp is formattable")
+}
+
+/// Escapes a TEXT value (RFC 5545 3.3.11).
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Appends a content line terminated by CRLF, folding it onto continuation
+/// lines that start with a space once it exceeds [`MAX_LINE_OCTETS`]. Folds
+/// never split a UTF-8 sequence.
+fn push_line(out: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            // The leading space counts towards the continuation line.
+            octets = 1;
+        }
+        out.push(c);
+        octets += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
@@ -0,0 +1,107 @@
+use crate::ShoppingItem;
+use crate::export::SCHEMA_VERSION;
+use serde_json::{Map, Value, json};
+
+/// An item parsed from an import, before it is inserted.
+#[derive(Debug)]
+pub struct ImportedItem {
+    pub name: String,
+    pub quantity: i32,
+}
+
+/// Parses a pasted plain-text list: one item per non-empty line, with an
+/// optional leading quantity such as `3x Eggs`.
+pub fn parse_text_list(text: &str) -> Vec<ImportedItem> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(parse_text_line)
+        .collect()
+}
+
+fn parse_text_line(line: &str) -> ImportedItem {
+    let quantity = line
+        .split_once(char::is_whitespace)
+        .and_then(|(prefix, rest)| {
+            let count = prefix.strip_suffix(['x', 'X'])?.parse::<i32>().ok()?;
+            let rest = rest.trim();
+            (count >= 1 && !rest.is_empty()).then_some((count, rest))
+        });
+
+    match quantity {
+        Some((quantity, name)) => ImportedItem {
+            name: name.to_string(),
+            quantity,
+        },
+        None => ImportedItem {
+            name: line.to_string(),
+            quantity: 1,
+        },
+    }
+}
+
+/// Reads a `GET /items/export.json` document, or a version 1 export (a bare
+/// array of items), bringing older items up to the current format first.
+/// Returns the version the document was written in along with its items.
+pub fn parse_json_export(doc: Value) -> Result<(u64, Vec<ShoppingItem>), String> {
+    let (version, items) = match doc {
+        Value::Array(items) => (1, items),
+        Value::Object(mut doc) => {
+            let version = match doc.get("schema_version") {
+                Some(version) => version
+                    .as_u64()
+                    .filter(|&version| version >= 1)
+                    .ok_or("schema_version must be a positive integer")?,
+                None => return Err("schema_version is missing".to_string()),
+            };
+            if version > SCHEMA_VERSION {
+                return Err(format!(
+                    "schema_version {} is newer than this server supports ({})",
+                    version, SCHEMA_VERSION
+                ));
+            }
+            match doc.remove("items") {
+                Some(Value::Array(items)) => (version, items),
+                _ => return Err("items must be an array".to_string()),
+            }
+        }
+        _ => return Err("Expected an export object or an array of items".to_string()),
+    };
+
+    let items = items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let Value::Object(mut item) = item else {
+                return Err(format!("item {}: expected an object", i));
+            };
+            if version < 2 {
+                upgrade_v1(&mut item).map_err(|e| format!("item {}: {}", i, e))?;
+            }
+            serde_json::from_value(Value::Object(item)).map_err(|e| format!("item {}: {}", i, e))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((version, items))
+}
+
+/// Items from before `quantity` existed have none, and early versions
+/// stored `is_shopped` as "true", "0" and the like.
+fn upgrade_v1(item: &mut Map<String, Value>) -> Result<(), String> {
+    if item.get("quantity").is_none_or(Value::is_null) {
+        item.insert("quantity".to_string(), json!(1));
+    }
+    let is_shopped = match item.get("is_shopped") {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(is_shopped)) => *is_shopped,
+        Some(Value::Number(n)) if n.as_i64() == Some(0) => false,
+        Some(Value::Number(n)) if n.as_i64() == Some(1) => true,
+        Some(Value::String(s)) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => return Err(format!("unrecognized is_shopped {:?}", s)),
+        },
+        Some(other) => return Err(format!("unrecognized is_shopped {}", other)),
+    };
+    item.insert("is_shopped".to_string(), json!(is_shopped));
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use std::env;
+
+/// Access log lines and errors answered by the request middleware.
+pub const HTTP: &str = "shoppinglist::http";
+/// Handler messages, such as items being added or imported.
+pub const API: &str = "shoppinglist::api";
+/// Migrations, slow queries and failed database calls.
+pub const DB: &str = "shoppinglist::db";
+/// Periodic database backups.
+pub const BACKUP: &str = "shoppinglist::backup";
+/// Configuration read at startup and binding the listener.
+pub const SERVER: &str = "shoppinglist::server";
+
+/// Sets up `env_logger` from `LOG_FILTER`, falling back to `RUST_LOG` and
+/// then to `info`. Both take `env_logger` directives, so
+/// `LOG_FILTER=info,shoppinglist::http=off` keeps everything but the access
+/// log.
+pub fn init() {
+    let filter = env::var("LOG_FILTER")
+        .or_else(|_| env::var("RUST_LOG"))
+        .unwrap_or_else(|_| "info".to_string());
+    env_logger::Builder::new()
+        .parse_write_style(&env::var("RUST_LOG_STYLE").unwrap_or_default())
+        .parse_filters(&filter)
+        .init();
+}
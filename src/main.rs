@@ -1,164 +1,4767 @@
-use actix_web::{App, HttpResponse, HttpServer, Responder, web};
-use env_logger;
-use log::{error, info};
-use rusqlite::{Connection, params};
+mod activity;
+mod auth;
+mod backup;
+mod categorize;
+mod db;
+mod debounce;
+mod debug;
+mod error;
+mod export;
+mod ical;
+mod import;
+mod logging;
+mod position;
+mod pretty;
+mod quantity;
+mod query_budget;
+mod read_only;
+mod request_id;
+mod security_headers;
+mod settings;
+mod share;
+mod stores;
+mod stream;
+mod timeout;
+mod timestamp;
+mod trips;
+mod word_filter;
+
+use actix_web::http::header::{self, ContentType, Header};
+use actix_web::middleware::{Compress, Condition, Logger, from_fn};
+use actix_web::{App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer, Responder, web};
+use auth::Admin;
+use error::{ApiError, with_transaction};
+use log::{error, info, warn};
+use request_id::RequestId;
+use rusqlite::{Connection, OptionalExtension, Row, Transaction, params, params_from_iter};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
 use std::env;
-use std::sync::Mutex;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ShoppingItem {
     id: Option<i32>,
     name: String,
     is_shopped: bool,
+    /// Estimated price, set when the item is added.
+    price_cents: Option<i64>,
+    /// Price actually paid, recorded by `PUT /items/{id}/purchase`.
+    paid_price_cents: Option<i64>,
+    shopped_at: Option<String>,
+    category: Option<String>,
+    #[serde(default = "default_quantity")]
+    quantity: i32,
+    /// Set by the database on every insert and update.
+    updated_at: Option<String>,
+    /// Pinned items are listed before all others.
+    #[serde(default)]
+    pinned: bool,
+    /// Link to a product photo; only http(s) URLs are accepted.
+    image_url: Option<String>,
+    /// RFC 3339 time the frontend should remind the user about the item.
+    remind_at: Option<String>,
+    /// Unit the quantity is measured in, e.g. "kg" or "L".
+    unit: Option<String>,
+    /// Measured amount in thousandths of `unit`, for quantities that aren't
+    /// whole numbers: 0.5 kg is `"amount_milli": 500`. Only describes the
+    /// item; price totals go by `quantity`.
+    amount_milli: Option<i64>,
+    /// List the item belongs to; defaults to the first list.
+    #[serde(default = "default_list_id")]
+    list_id: i64,
+    /// Place in the list's manual order, maintained by the server; use the
+    /// move endpoints to change it. When creating an item it may be set to
+    /// the 0-based index to insert the item at instead of the end.
+    position: Option<f64>,
+    /// Order within the item's category when grouped, e.g. to follow the
+    /// aisles of a particular store. Unset items come after the rest.
+    aisle_position: Option<i64>,
+    /// Set by the database when the item is added.
+    created_at: Option<String>,
+    /// `created_at` as "3 days ago", only with `?relative_time=true`.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    created_ago: Option<String>,
+    /// How many were actually bought, which may fall short of `quantity`.
+    /// Set by `PUT /items/{id}/purchase`, or to `quantity` when the item is
+    /// checked off some other way.
+    quantity_bought: Option<i32>,
+    /// Item this one is a sub-item of, e.g. "charcoal" under "BBQ supplies".
+    /// Must be on the same list.
+    parent_id: Option<i32>,
+    /// Sub-items, only with `?nested=true`.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    children: Option<Vec<ShoppingItem>>,
+    /// RFC 3339 time until which the item is hidden from `GET /items`; set
+    /// with `PUT /items/{id}/snooze`.
+    snoozed_until: Option<String>,
+    /// Scanned product code, unique across items; look an item up by it
+    /// with `GET /items/by-barcode/{code}`.
+    barcode: Option<String>,
 }
 
-struct AppState {
-    db: Mutex<Connection>,
+impl ShoppingItem {
+    /// Fills in the relative versions of the item's timestamps.
+    fn add_relative_times(&mut self, now: time::OffsetDateTime) {
+        self.created_ago = self
+            .created_at
+            .as_deref()
+            .and_then(timestamp::parse)
+            .map(|created_at| timestamp::ago(created_at, now));
+    }
 }
 
-async fn get_shopping_list(data: web::Data<AppState>) -> impl Responder {
-    let conn = match data.db.lock() {
-        Ok(conn) => conn,
-        Err(e) => {
-            error!("Failed to acquire database lock: {:?}", e);
-            return HttpResponse::InternalServerError().finish();
-        }
-    };
+/// Longest `image_url` accepted.
+const MAX_IMAGE_URL_LEN: usize = 2048;
+
+fn validate_image_url(image_url: &str) -> Result<(), String> {
+    if image_url.len() > MAX_IMAGE_URL_LEN {
+        return Err(format!(
+            "image_url must be at most {} characters",
+            MAX_IMAGE_URL_LEN
+        ));
+    }
+    match url::Url::parse(image_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => Err("image_url must be an absolute http or https URL".to_string()),
+    }
+}
+
+fn default_quantity() -> i32 {
+    1
+}
+
+fn default_list_id() -> i64 {
+    1
+}
+
+/// Longest barcode accepted; real ones are well under this.
+const MAX_BARCODE_LEN: usize = 64;
+
+/// Trims a barcode and rejects empty or overlong ones.
+fn normalize_barcode(barcode: &str) -> Result<String, String> {
+    let barcode = barcode.trim();
+    if barcode.is_empty() || barcode.len() > MAX_BARCODE_LEN {
+        return Err(format!(
+            "barcode must be between 1 and {} characters",
+            MAX_BARCODE_LEN
+        ));
+    }
+    Ok(barcode.to_string())
+}
+
+/// Largest `amount_milli` accepted, a million units.
+const MAX_AMOUNT_MILLI: i64 = 1_000_000_000;
 
-    let mut stmt = match conn.prepare("SELECT id, name, is_shopped FROM shopping_items ORDER BY id")
+/// Checks the client-supplied fields of an item before it is written,
+/// bringing timestamps into their stored form.
+fn validate_item(item: &mut ShoppingItem) -> Result<(), String> {
+    if item.quantity < 1 {
+        return Err("quantity must be at least 1".to_string());
+    }
+    if let Some(amount) = item.amount_milli
+        && !(1..=MAX_AMOUNT_MILLI).contains(&amount)
     {
-        Ok(stmt) => stmt,
-        Err(e) => {
-            error!("Failed to prepare SQL statement: {:?}", e);
-            return HttpResponse::InternalServerError().finish();
+        return Err(format!(
+            "amount_milli must be between 1 and {}",
+            MAX_AMOUNT_MILLI
+        ));
+    }
+    if let Some(image_url) = &item.image_url {
+        validate_image_url(image_url)?;
+    }
+    if let Some(remind_at) = &item.remind_at {
+        item.remind_at = Some(timestamp::normalize(remind_at)?);
+    }
+    if let Some(snoozed_until) = &item.snoozed_until {
+        item.snoozed_until = Some(timestamp::normalize(snoozed_until)?);
+    }
+    if let Some(barcode) = &item.barcode {
+        item.barcode = Some(normalize_barcode(barcode)?);
+    }
+    Ok(())
+}
+
+/// Column list matching [`item_from_row`].
+const ITEM_COLUMNS: &str = "id, name, is_shopped, price_cents, paid_price_cents, shopped_at, \
+                            category, quantity, updated_at, pinned, image_url, remind_at, unit,
+                            amount_milli, list_id, position,
+                            aisle_position, created_at, quantity_bought, parent_id,
+                            snoozed_until, barcode";
+
+fn item_from_row(row: &Row) -> rusqlite::Result<ShoppingItem> {
+    let is_shopped_int: i32 = row.get(2)?;
+    let pinned_int: i32 = row.get(9)?;
+    Ok(ShoppingItem {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        is_shopped: is_shopped_int != 0,
+        price_cents: row.get(3)?,
+        paid_price_cents: row.get(4)?,
+        shopped_at: row.get(5)?,
+        category: row.get(6)?,
+        quantity: row.get(7)?,
+        updated_at: row.get(8)?,
+        pinned: pinned_int != 0,
+        image_url: row.get(10)?,
+        remind_at: row.get(11)?,
+        unit: row.get(12)?,
+        amount_milli: row.get(13)?,
+        list_id: row.get(14)?,
+        position: row.get(15)?,
+        aisle_position: row.get(16)?,
+        created_at: row.get(17)?,
+        created_ago: None,
+        quantity_bought: row.get(18)?,
+        parent_id: row.get(19)?,
+        children: None,
+        snoozed_until: row.get(20)?,
+        barcode: row.get(21)?,
+    })
+}
+
+fn fetch_item(conn: &Connection, id: i32) -> rusqlite::Result<Option<ShoppingItem>> {
+    conn.query_row(
+        &format!("SELECT {} FROM shopping_items WHERE id = ?1", ITEM_COLUMNS),
+        [id],
+        item_from_row,
+    )
+    .optional()
+}
+
+/// `WHERE` conditions for listing items, with their positional parameters.
+#[derive(Debug, Default)]
+struct Filter {
+    conditions: Vec<String>,
+    params: Vec<rusqlite::types::Value>,
+    /// Parameters of the `ORDER BY` clause, bound after `params`.
+    order_params: Vec<rusqlite::types::Value>,
+    /// Which slice of the matching rows to return; all of them when `None`.
+    page: Option<Page>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Page {
+    limit: Option<u32>,
+    offset: u32,
+}
+
+impl Filter {
+    /// Adds a condition using a single `?` placeholder.
+    fn push(&mut self, condition: impl Into<String>, param: impl Into<rusqlite::types::Value>) {
+        self.conditions.push(condition.into());
+        self.params.push(param.into());
+    }
+
+    /// Adds `column IN (?, ?, ...)` with one placeholder per value.
+    fn push_in(&mut self, column: &str, values: impl IntoIterator<Item = i64>) {
+        let mut placeholders = Vec::new();
+        for value in values {
+            self.params.push(value.into());
+            placeholders.push("?");
         }
-    };
+        let condition = format!("{} IN ({})", column, placeholders.join(", "));
+        self.conditions.push(condition);
+    }
 
-    let items_result: Result<Vec<ShoppingItem>, rusqlite::Error> = stmt
-        .query_map([], |row| {
-            let is_shopped_int: i32 = row.get(2)?;
-            Ok(ShoppingItem {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                is_shopped: is_shopped_int != 0,
-            })
+    /// Adds a condition without parameters.
+    fn require(&mut self, condition: impl Into<String>) {
+        self.conditions.push(condition.into());
+    }
+
+    /// Parameters of a query with both the `WHERE` and `ORDER BY` clauses.
+    fn query_params(&self) -> impl Iterator<Item = &rusqlite::types::Value> {
+        self.params.iter().chain(&self.order_params)
+    }
+
+    fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            String::new()
+        } else {
+            format!(" WHERE {}", self.conditions.join(" AND "))
+        }
+    }
+
+    /// `LIMIT`/`OFFSET` to go after the `ORDER BY`.
+    fn page_clause(&self) -> String {
+        match self.page {
+            None => String::new(),
+            // SQLite only takes an offset after a limit; -1 means none.
+            Some(page) => format!(
+                " LIMIT {} OFFSET {}",
+                page.limit.map_or(-1, i64::from),
+                page.offset
+            ),
+        }
+    }
+
+    /// How many of `matching` rows the page keeps.
+    fn rows_on_page(&self, matching: usize) -> usize {
+        match self.page {
+            None => matching,
+            Some(page) => {
+                let rest = matching.saturating_sub(page.offset as usize);
+                page.limit.map_or(rest, |limit| rest.min(limit as usize))
+            }
+        }
+    }
+}
+
+fn count_items(conn: &Connection, filter: &Filter) -> rusqlite::Result<usize> {
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM shopping_items{}",
+            filter.where_clause()
+        ),
+        params_from_iter(&filter.params),
+        |row| row.get(0),
+    )
+}
+
+fn load_items(
+    conn: &Connection,
+    filter: &Filter,
+    order_by: &str,
+) -> rusqlite::Result<Vec<ShoppingItem>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM shopping_items{} ORDER BY {}{}",
+        ITEM_COLUMNS,
+        filter.where_clause(),
+        order_by,
+        filter.page_clause()
+    ))?;
+    stmt.query_map(params_from_iter(filter.query_params()), item_from_row)?
+        .collect()
+}
+
+fn list_exists(conn: &Connection, list_id: i64) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM lists WHERE id = ?1)",
+        [list_id],
+        |row| row.get(0),
+    )
+}
+
+fn unknown_list(list_id: i64) -> ApiError {
+    ApiError::BadRequest(format!("List {} does not exist", list_id))
+}
+
+struct AppState {
+    db: db::Pool,
+    /// Key required by the `/admin` endpoints; they are disabled when unset.
+    api_key: Option<String>,
+    name_matching: db::NameMatching,
+    /// Words rejected in item names, from `WORD_FILTER_PATH`.
+    word_filter: Option<word_filter::WordFilter>,
+    /// Keywords for `?auto_category=true`, extended by `CATEGORY_KEYWORDS_PATH`.
+    categorizer: categorize::Categorizer,
+    /// Repeated toggles to ignore, from `TOGGLE_DEBOUNCE_MS`.
+    toggle_debounce: Option<debounce::Debouncer>,
+    /// Largest `GET /items` response to send, from `MAX_RESPONSE_BYTES`.
+    max_response_bytes: Option<usize>,
+    /// Signs `GET /items/{id}/share` tokens, keyed by `SHARE_SECRET`.
+    share_signer: share::Signer,
+}
+
+impl AppState {
+    /// Rejects a name containing a filtered word with a 422.
+    fn check_name(&self, name: &str) -> Result<(), ApiError> {
+        match self.word_filter.as_ref().and_then(|f| f.blocked(name)) {
+            Some(word) => Err(ApiError::Unprocessable(format!(
+                "Item name contains a blocked word: {:?}",
+                word
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Columns a client may select with `GET /items?fields=`.
+const ITEM_FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "is_shopped",
+    "price_cents",
+    "paid_price_cents",
+    "shopped_at",
+    "category",
+    "quantity",
+    "updated_at",
+    "pinned",
+    "image_url",
+    "remind_at",
+    "unit",
+    "amount_milli",
+    "list_id",
+    "position",
+    "aisle_position",
+    "created_at",
+    "quantity_bought",
+    "parent_id",
+    "snoozed_until",
+    "barcode",
+];
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    fields: Option<String>,
+    sort: Option<String>,
+    /// Only return items changed after this RFC 3339 timestamp.
+    modified_since: Option<String>,
+    /// Stream the JSON response row by row. Defaults to streaming only when
+    /// the list is longer than [`STREAM_ROW_THRESHOLD`].
+    stream: Option<bool>,
+    /// Add `created_ago` strings next to `created_at`.
+    #[serde(default)]
+    relative_time: bool,
+    /// Return top-level items with their sub-items under `children`.
+    #[serde(default)]
+    nested: bool,
+    /// Only items priced at least this much.
+    min_price_cents: Option<i64>,
+    /// Only items priced at most this much.
+    max_price_cents: Option<i64>,
+    /// Keep items without a price when filtering by price.
+    #[serde(default)]
+    include_unpriced: bool,
+    /// Return at most this many items.
+    limit: Option<u32>,
+    /// Skip this many items first.
+    offset: Option<u32>,
+    /// Also list items snoozed until a later time.
+    #[serde(default)]
+    include_snoozed: bool,
+    /// Only these comma-separated ids, snoozed or not. Unknown ids are
+    /// skipped.
+    ids: Option<String>,
+    /// Order items as a walk through this store, following its layout.
+    route: Option<String>,
+}
+
+/// Most ids accepted by `GET /items?ids=`.
+const MAX_QUERY_IDS: usize = 500;
+
+/// Parses the comma-separated `ids` parameter.
+fn parse_ids(ids: &str) -> Result<Vec<i64>, String> {
+    let ids = ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map_err(|_| format!("'{}' in ids is not a valid id", id))
         })
-        .and_then(|iter| iter.collect());
+        .collect::<Result<Vec<i64>, _>>()?;
+    if ids.is_empty() {
+        return Err("ids must list at least one id".to_string());
+    }
+    if ids.len() > MAX_QUERY_IDS {
+        return Err(format!("ids must have at most {} entries", MAX_QUERY_IDS));
+    }
+    Ok(ids)
+}
+
+/// Lists longer than this are streamed unless `?stream=false` is given.
+const STREAM_ROW_THRESHOLD: usize = 1000;
+
+/// Rough size of a serialized item, to check a list against
+/// `MAX_RESPONSE_BYTES` before loading it.
+const ESTIMATED_ITEM_BYTES: usize = 450;
+/// The same for each field selected with `?fields=`.
+const ESTIMATED_FIELD_BYTES: usize = 32;
 
-    match items_result {
-        Ok(items) => {
-            info!("Successfully retrieved {} items", items.len());
-            HttpResponse::Ok().json(items)
+impl ListQuery {
+    fn filter(&self) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        if let Some(since) = &self.modified_since {
+            filter.push("updated_at > ?", timestamp::normalize(since)?);
+        }
+        if let (Some(min), Some(max)) = (self.min_price_cents, self.max_price_cents)
+            && min > max
+        {
+            return Err("min_price_cents must not be greater than max_price_cents".to_string());
+        }
+        let unpriced = if self.include_unpriced {
+            "price_cents IS NULL OR "
+        } else {
+            ""
+        };
+        if let Some(min) = self.min_price_cents {
+            filter.push(format!("({}price_cents >= ?)", unpriced), min);
+        }
+        if let Some(max) = self.max_price_cents {
+            filter.push(format!("({}price_cents <= ?)", unpriced), max);
+        }
+        if let Some(ids) = &self.ids {
+            filter.push_in("id", parse_ids(ids)?);
         }
-        Err(e) => {
-            error!("Failed to retrieve shopping items: {:?}", e);
-            HttpResponse::InternalServerError().finish()
+        if !self.include_snoozed && self.ids.is_none() {
+            filter.push(
+                "(snoozed_until IS NULL OR snoozed_until <= ?)",
+                timestamp::format(time::OffsetDateTime::now_utc()),
+            );
         }
+        if self.limit.is_some() || self.offset.is_some() {
+            filter.page = Some(Page {
+                limit: self.limit,
+                offset: self.offset.unwrap_or(0),
+            });
+        }
+        Ok(filter)
+    }
+
+    fn filters_price(&self) -> bool {
+        self.min_price_cents.is_some() || self.max_price_cents.is_some()
     }
 }
 
-async fn add_item(item: web::Json<ShoppingItem>, data: web::Data<AppState>) -> impl Responder {
-    let conn = data.db.lock().unwrap();
-    let result = conn.execute(
-        "INSERT INTO shopping_items (name, is_shopped) VALUES (?1, ?2)",
-        params![item.name, item.is_shopped],
-    );
+const X_TOTAL_PRICE_CENTS: header::HeaderName =
+    header::HeaderName::from_static("x-total-price-cents");
+
+/// What the items matching `filter` cost together, each price counted once
+/// per unit of quantity. Pagination is ignored.
+fn total_price_cents(conn: &Connection, filter: &Filter) -> rusqlite::Result<i64> {
+    conn.query_row(
+        &format!(
+            "SELECT COALESCE(SUM(price_cents * quantity), 0) FROM shopping_items{}",
+            filter.where_clause()
+        ),
+        params_from_iter(&filter.params),
+        |row| row.get(0),
+    )
+}
+
+/// Order of `GET /items` without a `sort` parameter.
+const DEFAULT_ORDER: &str = "pinned DESC, list_id, position, id";
 
-    match result {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+/// Maps the `sort` parameter of `GET /items` to an `ORDER BY` clause.
+fn order_by(sort: Option<&str>) -> Result<&'static str, String> {
+    match sort {
+        None | Some("position") => Ok(DEFAULT_ORDER),
+        Some("id") => Ok("pinned DESC, id"),
+        // Must match db::NAME_COLLATION.
+        Some("name") => Ok("pinned DESC, name COLLATE NAME_NOCASE, id"),
+        Some(other) => Err(format!(
+            "Unknown sort '{}', expected one of: position, id, name",
+            other
+        )),
     }
 }
 
-async fn update_item_status(item_id: web::Path<i32>, data: web::Data<AppState>) -> impl Responder {
-    let conn = data.db.lock().unwrap();
-    let result = conn.execute(
-        "UPDATE shopping_items SET is_shopped = 1 - is_shopped WHERE id = ?1",
-        [item_id.into_inner()],
+/// Resolves a comma-separated `fields` parameter against [`ITEM_FIELDS`],
+/// returning the whitelisted column names in the order they were requested.
+fn parse_fields(raw: &str) -> Result<Vec<&'static str>, String> {
+    let mut fields = Vec::new();
+    for name in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        match ITEM_FIELDS.iter().find(|field| **field == name) {
+            Some(field) if !fields.contains(field) => fields.push(*field),
+            Some(_) => {}
+            None => return Err(format!("Unknown field '{}'", name)),
+        }
+    }
+
+    if fields.is_empty() {
+        return Err("No fields requested".to_string());
+    }
+    Ok(fields)
+}
+
+fn field_value(row: &Row, idx: usize, field: &str) -> rusqlite::Result<Value> {
+    if matches!(field, "is_shopped" | "pinned") {
+        let bool_int: i32 = row.get(idx)?;
+        return Ok(Value::Bool(bool_int != 0));
+    }
+
+    Ok(match row.get::<_, rusqlite::types::Value>(idx)? {
+        rusqlite::types::Value::Null => Value::Null,
+        rusqlite::types::Value::Integer(i) => Value::from(i),
+        rusqlite::types::Value::Real(f) => Value::from(f),
+        rusqlite::types::Value::Text(s) => Value::String(s),
+        rusqlite::types::Value::Blob(b) => Value::from(b),
+    })
+}
+
+fn get_selected_fields(
+    conn: &Connection,
+    fields: &[&str],
+    filter: &Filter,
+    order_by: &str,
+    relative_time: bool,
+    total_price: Option<i64>,
+    request_id: &RequestId,
+) -> Result<HttpResponse, ApiError> {
+    // Only names from ITEM_FIELDS ever reach the SQL string.
+    let sql = format!(
+        "SELECT {} FROM shopping_items{} ORDER BY {}{}",
+        fields.join(", "),
+        filter.where_clause(),
+        order_by,
+        filter.page_clause()
     );
 
-    match result {
-        Ok(_) => HttpResponse::Ok().finish(),
-        Err(_) => HttpResponse::InternalServerError().finish(),
+    let mut stmt = conn.prepare(&sql)?;
+    let now = time::OffsetDateTime::now_utc();
+    let items = stmt
+        .query_map(params_from_iter(filter.query_params()), |row| {
+            let mut item = Map::new();
+            for (idx, field) in fields.iter().enumerate() {
+                item.insert(field.to_string(), field_value(row, idx, field)?);
+            }
+            if relative_time {
+                let created_ago = item
+                    .get("created_at")
+                    .and_then(Value::as_str)
+                    .and_then(timestamp::parse)
+                    .map(|created_at| timestamp::ago(created_at, now));
+                if let Some(created_ago) = created_ago {
+                    item.insert("created_ago".to_string(), Value::String(created_ago));
+                }
+            }
+            Ok(item)
+        })?
+        .collect::<rusqlite::Result<Vec<Map<String, Value>>>>()?;
+
+    info!(
+        target: logging::API,
+        "[{}] Successfully retrieved {} items",
+        request_id,
+        items.len()
+    );
+    Ok(items_json(&mut HttpResponse::Ok(), items, total_price))
+}
+
+/// Moves items under their parents' `children`, keeping the order within
+/// each level. Items whose parent isn't among `items` stay at the top.
+fn nest(items: Vec<ShoppingItem>) -> Vec<ShoppingItem> {
+    use std::collections::{HashMap, HashSet};
+
+    fn attach(item: &mut ShoppingItem, children: &mut HashMap<i32, Vec<ShoppingItem>>) {
+        let Some(mut kids) = item.id.and_then(|id| children.remove(&id)) else {
+            return;
+        };
+        for kid in &mut kids {
+            attach(kid, children);
+        }
+        item.children = Some(kids);
+    }
+
+    let ids: HashSet<i32> = items.iter().filter_map(|item| item.id).collect();
+    let mut roots = Vec::new();
+    let mut children: HashMap<i32, Vec<ShoppingItem>> = HashMap::new();
+    for item in items {
+        match item.parent_id {
+            Some(parent_id) if ids.contains(&parent_id) => {
+                children.entry(parent_id).or_default().push(item)
+            }
+            _ => roots.push(item),
+        }
+    }
+    for root in &mut roots {
+        attach(root, &mut children);
     }
+    // Only a cycle, which writes reject, leaves anything unattached; don't
+    // drop those items.
+    roots.extend(children.into_values().flatten());
+    roots
 }
 
-async fn swap_items(items: web::Json<(i32, i32)>, data: web::Data<AppState>) -> impl Responder {
-    let (id1, id2) = items.into_inner(); // Extract the tuple from web::Json
+/// Whether the client ranks `text/plain` above JSON in its `Accept` header.
+/// Anything else, including no header at all, gets JSON.
+fn prefers_plain_text(req: &HttpRequest) -> bool {
+    let Ok(accept) = header::Accept::parse(req) else {
+        return false;
+    };
+    for mime in accept.ranked() {
+        match mime.essence_str() {
+            "text/plain" => return true,
+            "application/json" | "application/*" | "*/*" => return false,
+            _ => {}
+        }
+    }
+    false
+}
 
-    let mut conn = data.db.lock().unwrap();
-    let transaction = conn.transaction().unwrap();
+/// Link to the current path and query with `limit` and `offset` replaced.
+fn page_link(req: &HttpRequest, limit: usize, offset: u32) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for (key, value) in url::form_urlencoded::parse(req.query_string().as_bytes()) {
+        if key != "limit" && key != "offset" {
+            query.append_pair(&key, &value);
+        }
+    }
+    query
+        .append_pair("limit", &limit.to_string())
+        .append_pair("offset", &offset.to_string());
+    format!("{}?{}", req.path(), query.finish())
+}
 
-    // Get the current positions of the items
-    let ids: Vec<i32> = {
-        let mut stmt = transaction
-            .prepare("SELECT id FROM shopping_items WHERE id IN (?1, ?2) ORDER BY id")
-            .unwrap();
-        let rows = stmt.query_map(&[&id1, &id2], |row| row.get(0)).unwrap();
-        rows.map(|r| r.unwrap()).collect()
+async fn get_shopping_list(
+    req: HttpRequest,
+    query: web::Query<ListQuery>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let fields = match query.fields.as_deref().map(parse_fields).transpose() {
+        Ok(fields) => fields,
+        Err(message) => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "error": message,
+                "valid_fields": ITEM_FIELDS,
+            })));
+        }
     };
 
-    if ids.len() != 2 {
-        return HttpResponse::BadRequest().finish();
+    let order_by = match &query.route {
+        Some(_) if query.sort.is_some() => {
+            return Err(ApiError::BadRequest(
+                "route cannot be combined with sort".to_string(),
+            ));
+        }
+        Some(_) => stores::ROUTE_ORDER,
+        None => order_by(query.sort.as_deref()).map_err(ApiError::BadRequest)?,
+    };
+
+    let mut filter = query.filter().map_err(ApiError::BadRequest)?;
+
+    if query.nested && fields.is_some() {
+        return Err(ApiError::BadRequest(
+            "nested cannot be combined with fields".to_string(),
+        ));
     }
 
-    let (id1, id2) = (ids[0], ids[1]);
+    let conn = data.db.get()?;
 
-    // Swap the positions
-    transaction
-        .execute("UPDATE shopping_items SET id = -1 WHERE id = ?1", &[&id1])
-        .unwrap();
-    transaction
-        .execute(
-            "UPDATE shopping_items SET id = ?1 WHERE id = ?2",
-            &[&id1, &id2],
-        )
-        .unwrap();
-    transaction
-        .execute("UPDATE shopping_items SET id = ?1 WHERE id = -1", &[&id2])
-        .unwrap();
+    if let Some(store) = &query.route {
+        if stores::layout(&conn, store)?.is_empty() {
+            return Err(ApiError::NotFound(format!(
+                "Store '{}' has no layout",
+                store
+            )));
+        }
+        filter.order_params.push(store.clone().into());
+    }
 
-    transaction.commit().unwrap();
+    if let Some(max_bytes) = data.max_response_bytes
+        && !prefers_plain_text(&req)
+    {
+        let rows = filter.rows_on_page(count_items(&conn, &filter)?);
+        let row_bytes = fields.as_ref().map_or(ESTIMATED_ITEM_BYTES, |fields| {
+            fields.len() * ESTIMATED_FIELD_BYTES
+        });
+        if rows * row_bytes > max_bytes {
+            let limit = (max_bytes / row_bytes).max(1);
+            warn!(
+                target: logging::API,
+                "[{}] Refused to send {} items, about {} bytes",
+                request_id,
+                rows,
+                rows * row_bytes
+            );
+            return Ok(HttpResponse::PayloadTooLarge().json(json!({
+                "error": "list too large, use pagination",
+                "estimated_bytes": rows * row_bytes,
+                "max_bytes": max_bytes,
+                "next": page_link(&req, limit, filter.page.map_or(0, |page| page.offset)),
+            })));
+        }
+    }
 
-    HttpResponse::Ok().finish()
+    list_response(&req, &query, conn, fields, filter, order_by, request_id)
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
-    let conn = Connection::open("shopping_list.db").unwrap();
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS shopping_items (
-            id INTEGER PRIMARY KEY,
-            name TEXT NOT NULL,
-            is_shopped BOOLEAN NOT NULL      
-        )",
-        [],
-    )
-    .unwrap();
+fn list_response(
+    req: &HttpRequest,
+    query: &ListQuery,
+    conn: db::PooledConnection,
+    fields: Option<Vec<&'static str>>,
+    filter: Filter,
+    order_by: &'static str,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let plain_text = prefers_plain_text(req);
+    // Price-filtered lists also report what the matching items add up to.
+    let total_price = if query.filters_price() {
+        Some(total_price_cents(&conn, &filter)?)
+    } else {
+        None
+    };
+    if let (Some(fields), false) = (fields, plain_text) {
+        return get_selected_fields(
+            &conn,
+            &fields,
+            &filter,
+            order_by,
+            query.relative_time,
+            total_price,
+            &request_id,
+        );
+    }
 
-    let app_state = web::Data::new(AppState {
-        db: Mutex::new(conn),
+    // Nesting needs every item at hand, and the price total goes after
+    // them, so neither is streamed.
+    if !plain_text && !query.nested && total_price.is_none() {
+        let stream = match query.stream {
+            Some(stream) => stream,
+            None => filter.rows_on_page(count_items(&conn, &filter)?) > STREAM_ROW_THRESHOLD,
+        };
+        if stream {
+            return Ok(stream::items(
+                conn,
+                filter,
+                order_by,
+                query.relative_time,
+                request_id,
+            ));
+        }
+    }
+
+    let mut items = load_items(&conn, &filter, order_by)?;
+    info!(
+        target: logging::API,
+        "[{}] Successfully retrieved {} items",
+        request_id,
+        items.len()
+    );
+    if query.relative_time {
+        let now = time::OffsetDateTime::now_utc();
+        for item in &mut items {
+            item.add_relative_times(now);
+        }
+    }
+    if query.nested && !plain_text {
+        items = nest(items);
+    }
+    let mut response = HttpResponse::Ok();
+    response.insert_header((header::VARY, "Accept"));
+    if plain_text {
+        if let Some(total) = total_price {
+            response.insert_header((X_TOTAL_PRICE_CENTS, total));
+        }
+        Ok(response
+            .content_type(ContentType::plaintext())
+            .body(export::plain_text(&items)))
+    } else {
+        Ok(items_json(&mut response, items, total_price))
+    }
+}
+
+/// Responds with `items`, or with `{ "items": ..., "total_price_cents": ... }`
+/// when the list was filtered by price. The total is also sent as
+/// `X-Total-Price-Cents`, as it is for plain text lists.
+fn items_json(
+    response: &mut HttpResponseBuilder,
+    items: impl Serialize,
+    total_price: Option<i64>,
+) -> HttpResponse {
+    match total_price {
+        Some(total) => response
+            .insert_header((X_TOTAL_PRICE_CENTS, total))
+            .json(json!({ "items": items, "total_price_cents": total })),
+        None => response.json(items),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SortQuery {
+    sort: Option<String>,
+}
+
+/// Returns the list split into what is still to buy and what was bought, in
+/// one read.
+async fn get_sections(
+    query: web::Query<SortQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let order_by = order_by(query.sort.as_deref()).map_err(ApiError::BadRequest)?;
+    let conn = data.db.get()?;
+    let (bought, to_buy): (Vec<_>, Vec<_>) = load_items(&conn, &Filter::default(), order_by)?
+        .into_iter()
+        .partition(|item| item.is_shopped);
+    Ok(HttpResponse::Ok().json(json!({ "to_buy": to_buy, "bought": bought })))
+}
+
+#[derive(Debug, Deserialize)]
+struct DueQuery {
+    /// RFC 3339 cutoff; defaults to now.
+    before: Option<String>,
+}
+
+/// Lists unshopped items whose reminder falls before the cutoff, soonest
+/// first.
+async fn get_due_items(
+    query: web::Query<DueQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let before = match &query.before {
+        Some(before) => timestamp::normalize(before).map_err(ApiError::BadRequest)?,
+        None => timestamp::format(time::OffsetDateTime::now_utc()),
+    };
+
+    let mut filter = Filter::default();
+    filter.push("remind_at < ?", before);
+    filter.require("is_shopped = 0");
+
+    let conn = data.db.get()?;
+    let items = load_items(&conn, &filter, "remind_at, id")?;
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[derive(Debug, Deserialize)]
+struct StaleQuery {
+    /// How many days an item must have been on the list.
+    days: Option<u32>,
+}
+
+const DEFAULT_STALE_DAYS: u32 = 14;
+/// Ten years, far past anything on a shopping list.
+const MAX_STALE_DAYS: u32 = 3650;
+
+/// Lists unshopped items added more than `days` ago, oldest first, so
+/// things that keep not being bought can be pruned.
+async fn get_stale_items(
+    query: web::Query<StaleQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let days = query.days.unwrap_or(DEFAULT_STALE_DAYS);
+    if !(1..=MAX_STALE_DAYS).contains(&days) {
+        return Err(ApiError::BadRequest(format!(
+            "days must be between 1 and {}",
+            MAX_STALE_DAYS
+        )));
+    }
+    let cutoff = time::OffsetDateTime::now_utc() - time::Duration::days(days.into());
+
+    let mut filter = Filter::default();
+    filter.push("created_at < ?", timestamp::format(cutoff));
+    filter.require("is_shopped = 0");
+
+    let conn = data.db.get()?;
+    let items = load_items(&conn, &filter, "created_at, id")?;
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpensiveQuery {
+    limit: Option<u32>,
+    /// Only rank this list's items.
+    list_id: Option<i64>,
+}
+
+const DEFAULT_EXPENSIVE_LIMIT: u32 = 5;
+const MAX_EXPENSIVE_LIMIT: u32 = 100;
+
+/// Lists the unshopped items costing the most in total (`price_cents` times
+/// `quantity`), most expensive first. Items without a price are left out.
+async fn get_expensive_items(
+    query: web::Query<ExpensiveQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let mut filter = Filter::default();
+    filter.require("is_shopped = 0");
+    filter.require("price_cents IS NOT NULL");
+    if let Some(list_id) = query.list_id {
+        filter.push("list_id = ?", list_id);
+    }
+    filter.page = Some(Page {
+        limit: Some(
+            query
+                .limit
+                .unwrap_or(DEFAULT_EXPENSIVE_LIMIT)
+                .min(MAX_EXPENSIVE_LIMIT),
+        ),
+        offset: 0,
     });
 
-    let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-    let port = env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse()
-        .unwrap();
+    let conn = data.db.get()?;
+    let items = load_items(&conn, &filter, "price_cents * quantity DESC, id")?;
+    Ok(HttpResponse::Ok().json(items))
+}
 
-    println!("Server running at http://{}:{}", host, port);
+/// Order for lists grouped by category: store layout first, then name.
+const AISLE_ORDER: &str = "aisle_position IS NULL, aisle_position, name COLLATE NAME_NOCASE, id";
 
-    HttpServer::new(move || {
-        App::new()
-            .app_data(app_state.clone())
-            .route("/items", web::get().to(get_shopping_list))
-            .route("/items", web::post().to(add_item))
-            .route("/items/{id}/toggle", web::put().to(update_item_status))
-            .route("/items/swap", web::put().to(swap_items))
-    })
-    .bind((host, port))?
-    .run()
-    .await
+async fn export_markdown(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    let items = load_items(&conn, &Filter::default(), AISLE_ORDER)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/markdown; charset=utf-8")
+        .body(export::markdown(&items)))
+}
+
+async fn export_json(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    let order_by = order_by(None).map_err(ApiError::BadRequest)?;
+    let items = load_items(&conn, &Filter::default(), order_by)?;
+    Ok(HttpResponse::Ok().json(export::json(&items, time::OffsetDateTime::now_utc())))
+}
+
+/// Checks imported items against each other and the lists they name, since
+/// `insert_item`'s checks look at the items already stored.
+fn check_imported(conn: &Connection, items: &[ShoppingItem]) -> Result<(), ApiError> {
+    use std::collections::HashMap;
+
+    let mut lists = HashMap::new();
+    let mut barcodes = HashMap::new();
+    for (i, item) in items.iter().enumerate() {
+        if let Some(id) = item.id
+            && lists.insert(id, item.list_id).is_some()
+        {
+            return Err(ApiError::BadRequest(format!(
+                "item {}: id {} is repeated",
+                i, id
+            )));
+        }
+        if let Some(barcode) = &item.barcode
+            && barcodes.insert(barcode, i).is_some()
+        {
+            return Err(ApiError::BadRequest(format!(
+                "item {}: barcode {} is repeated",
+                i, barcode
+            )));
+        }
+    }
+    for (i, item) in items.iter().enumerate() {
+        if !list_exists(conn, item.list_id)? {
+            return Err(ApiError::BadRequest(format!(
+                "item {}: list {} does not exist",
+                i, item.list_id
+            )));
+        }
+        if let Some(parent_id) = item.parent_id
+            && lists.get(&parent_id) != Some(&item.list_id)
+        {
+            return Err(ApiError::BadRequest(format!(
+                "item {}: parent {} is not an item on list {}",
+                i, parent_id, item.list_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects imported sub-items whose parents lead back to them. Runs once the
+/// items are stored, so [`makes_cycle`] can walk them like `check_parent`
+/// does.
+fn check_imported_cycles(tx: &Transaction, items: &[ShoppingItem]) -> Result<(), ApiError> {
+    for (i, item) in items.iter().enumerate() {
+        if let (Some(id), Some(parent_id)) = (item.id, item.parent_id)
+            && makes_cycle(tx, id, parent_id)?
+        {
+            return Err(ApiError::BadRequest(format!(
+                "item {}: parent {} would make a cycle",
+                i, parent_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Inserts exported items with every stored column as it was.
+fn restore_items(tx: &Transaction, items: &[ShoppingItem]) -> rusqlite::Result<usize> {
+    let mut stmt = tx.prepare(&format!(
+        "INSERT INTO shopping_items ({})
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                 ?18, ?19, ?20, ?21, ?22)",
+        ITEM_COLUMNS
+    ))?;
+    for item in items {
+        stmt.execute(params![
+            item.id,
+            item.name,
+            item.is_shopped,
+            item.price_cents,
+            item.paid_price_cents,
+            item.shopped_at,
+            item.category,
+            item.quantity,
+            item.updated_at,
+            item.pinned,
+            item.image_url,
+            item.remind_at,
+            item.unit,
+            item.amount_milli,
+            item.list_id,
+            item.position,
+            item.aisle_position,
+            item.created_at,
+            item.quantity_bought,
+            item.parent_id,
+            item.snoozed_until,
+            item.barcode
+        ])?;
+    }
+    Ok(items.len())
+}
+
+/// Restores a `GET /items/export.json` document, replacing every item in one
+/// transaction. Ids, positions and timestamps are kept, so exporting again
+/// gives back the same items. Since it wipes the current list, it needs the
+/// admin key.
+async fn import_json(
+    _: Admin,
+    body: web::Json<Value>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let (version, mut items) =
+        import::parse_json_export(body.into_inner()).map_err(ApiError::BadRequest)?;
+    for (i, item) in items.iter_mut().enumerate() {
+        validate_item(item).map_err(|e| ApiError::BadRequest(format!("item {}: {}", i, e)))?;
+        data.check_name(&item.name)?;
+    }
+
+    let mut conn = data.db.get()?;
+    let imported = with_transaction(&mut conn, |tx| {
+        check_imported(tx, &items)?;
+        tx.execute("DELETE FROM shopping_items", [])?;
+        let imported = restore_items(tx, &items)?;
+        check_imported_cycles(tx, &items)?;
+        Ok(imported)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Restored {} items from a version {} export", request_id, imported, version
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "imported": imported,
+        "schema_version": version,
+    })))
+}
+
+async fn export_todos(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    let order_by = order_by(None).map_err(ApiError::BadRequest)?;
+    let items = load_items(&conn, &Filter::default(), order_by)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(ical::todos(&items, time::OffsetDateTime::now_utc())))
+}
+
+#[derive(Debug, Deserialize)]
+struct AutocompleteQuery {
+    #[serde(default)]
+    q: String,
+    limit: Option<u32>,
+}
+
+const DEFAULT_AUTOCOMPLETE_LIMIT: u32 = 10;
+const MAX_AUTOCOMPLETE_LIMIT: u32 = 50;
+
+/// Escapes `%`, `_` and the escape character itself for use in a
+/// `LIKE ... ESCAPE '\'` pattern.
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn autocomplete_names(
+    conn: &Connection,
+    prefix: &str,
+    limit: u32,
+    matching: db::NameMatching,
+) -> rusqlite::Result<Vec<String>> {
+    let (sql, pattern) = autocomplete_query(prefix, matching);
+    let mut stmt = conn.prepare(&sql)?;
+    stmt.query_map(params![pattern, limit], |row| row.get(0))?
+        .collect()
+}
+
+/// The query behind [`autocomplete_names`] and the pattern to bind to its
+/// `?1`; `?2` is the limit.
+fn autocomplete_query(prefix: &str, matching: db::NameMatching) -> (String, String) {
+    // Names are suggested by how often they appear on the list and in the
+    // purchase history. Unless names are case-sensitive, differently-cased
+    // spellings count as one.
+    // The case-insensitive LIKE can use the NOCASE name indexes.
+    let (condition, pattern) = match matching {
+        db::NameMatching::CaseInsensitive => (
+            "name LIKE ?1 ESCAPE '\\'",
+            format!("{}%", escape_like(prefix)),
+        ),
+        db::NameMatching::CaseSensitive => ("substr(name, 1, length(?1)) = ?1", prefix.to_string()),
+    };
+    let sql = format!(
+        "SELECT name FROM (
+             SELECT name FROM shopping_items
+             UNION ALL
+             SELECT name FROM purchase_history
+         )
+         WHERE {condition}
+         GROUP BY name COLLATE {collation}
+         ORDER BY COUNT(*) DESC, name COLLATE NAME_NOCASE
+         LIMIT ?2",
+        collation = matching.collation()
+    );
+    (sql, pattern)
+}
+
+async fn autocomplete(
+    query: web::Query<AutocompleteQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUTOCOMPLETE_LIMIT)
+        .min(MAX_AUTOCOMPLETE_LIMIT);
+    let conn = data.db.get()?;
+    let names = autocomplete_names(&conn, query.q.trim(), limit, data.name_matching)?;
+    Ok(HttpResponse::Ok().json(names))
+}
+
+#[derive(Debug, Deserialize)]
+struct PairsQuery {
+    limit: Option<u32>,
+}
+
+const DEFAULT_PAIRS_LIMIT: u32 = 10;
+const MAX_PAIRS_LIMIT: u32 = 50;
+
+/// Purchases less than this far apart count as one shopping trip, in days
+/// as used by SQLite's `julianday`.
+const TRIP_GAP_DAYS: f64 = 2.0 / 24.0;
+
+#[derive(Debug, Serialize)]
+struct Pair {
+    name: String,
+    /// Trips in which both were bought.
+    count: i64,
+}
+
+/// Names bought on the same trips as `name`, most often first. Trips are
+/// runs of purchases with no gap longer than [`TRIP_GAP_DAYS`].
+fn paired_names(
+    conn: &Connection,
+    name: &str,
+    limit: u32,
+    matching: db::NameMatching,
+) -> rusqlite::Result<Vec<Pair>> {
+    let mut stmt = conn.prepare(&format!(
+        "WITH gaps AS (
+             SELECT id, name, purchased_at,
+                    julianday(purchased_at)
+                        - julianday(LAG(purchased_at) OVER (ORDER BY purchased_at, id)) AS gap
+             FROM purchase_history
+         ),
+         trips AS (
+             SELECT name,
+                    SUM(gap IS NULL OR gap > ?3) OVER (ORDER BY purchased_at, id ROWS UNBOUNDED PRECEDING)
+                        AS trip
+             FROM gaps
+         )
+         SELECT other.name, COUNT(DISTINCT other.trip)
+         FROM trips target
+         JOIN trips other
+           ON other.trip = target.trip AND other.name != target.name COLLATE {collation}
+         WHERE target.name = ?1 COLLATE {collation}
+         GROUP BY other.name COLLATE {collation}
+         ORDER BY COUNT(DISTINCT other.trip) DESC, other.name COLLATE NAME_NOCASE
+         LIMIT ?2",
+        collation = matching.collation()
+    ))?;
+    stmt.query_map(params![name, limit, TRIP_GAP_DAYS], |row| {
+        Ok(Pair {
+            name: row.get(0)?,
+            count: row.get(1)?,
+        })
+    })?
+    .collect()
+}
+
+/// Items often bought together with this one, from the purchase history,
+/// for "people who buy pasta also buy sauce" suggestions.
+async fn get_item_pairs(
+    item_id: web::Path<i32>,
+    query: web::Query<PairsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAIRS_LIMIT)
+        .min(MAX_PAIRS_LIMIT);
+    let conn = data.db.get()?;
+    let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+    let pairs = paired_names(&conn, &item.name, limit, data.name_matching)?;
+    Ok(HttpResponse::Ok().json(pairs))
+}
+
+#[derive(Debug, Deserialize)]
+struct ActivityQuery {
+    limit: Option<u32>,
+}
+
+const DEFAULT_ACTIVITY_LIMIT: u32 = 50;
+const MAX_ACTIVITY_LIMIT: u32 = 500;
+
+/// Recent changes to items, newest first.
+async fn get_activity(
+    query: web::Query<ActivityQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_ACTIVITY_LIMIT)
+        .min(MAX_ACTIVITY_LIMIT);
+    let conn = data.db.get()?;
+    Ok(HttpResponse::Ok().json(activity::recent(&conn, limit)?))
+}
+
+/// An item's direct sub-items, in list order.
+async fn get_item_children(
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let conn = data.db.get()?;
+    if fetch_item(&conn, item_id)?.is_none() {
+        return Err(ApiError::item_not_found(item_id));
+    }
+    let mut filter = Filter::default();
+    filter.push("parent_id = ?", item_id);
+    let order_by = order_by(None).map_err(ApiError::BadRequest)?;
+    Ok(HttpResponse::Ok().json(load_items(&conn, &filter, order_by)?))
+}
+
+/// Logged changes to one item, oldest first. An item with no changes left
+/// in the log gets an empty array; an id that matches neither an item nor
+/// any log entry is a 404.
+async fn get_item_history(
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let conn = data.db.get()?;
+    let entries = activity::for_item(&conn, item_id)?;
+    if entries.is_empty() && fetch_item(&conn, item_id)?.is_none() {
+        return Err(ApiError::item_not_found(item_id));
+    }
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+/// How often toggles too old to debounce are forgotten.
+const DEBOUNCE_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically forgets toggles that can no longer be debounced.
+async fn prune_toggle_debounce(data: web::Data<AppState>) {
+    let Some(debouncer) = &data.toggle_debounce else {
+        return;
+    };
+    let mut interval = actix_web::rt::time::interval(DEBOUNCE_PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        debouncer.prune();
+    }
+}
+
+/// How often old activity is pruned.
+const AUDIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes activity older than the retention period, at startup and then
+/// every [`AUDIT_PRUNE_INTERVAL`].
+async fn prune_audit_log(data: web::Data<AppState>, retention_days: u32) {
+    let mut interval = actix_web::rt::time::interval(AUDIT_PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let result = data
+            .db
+            .get()
+            .map_err(ApiError::from)
+            .and_then(|conn| Ok(activity::prune(&conn, retention_days)?));
+        match result {
+            Ok(0) => {}
+            Ok(pruned) => info!(target: logging::DB, "Pruned {} activity entries", pruned),
+            Err(e) => warn!(target: logging::DB, "Failed to prune activity: {}", e),
+        }
+    }
+}
+
+/// Routes listed by `GET /`. Keep in sync with the routes registered in
+/// `main`.
+const ENDPOINTS: &[&str] = &[
+    "GET /",
+    "GET /version",
+    "GET /autocomplete?q=",
+    "GET /activity?limit=",
+    "GET /trips?limit=",
+    "GET /stores/{name}/layout",
+    "PUT /stores/{name}/layout",
+    "POST /history/readd",
+    "POST /admin/vacuum",
+    "POST /admin/repair",
+    "GET /admin/connections",
+    "GET /settings",
+    "PUT /settings",
+    "GET /lists",
+    "POST /lists",
+    "GET /lists/summary",
+    "PUT /lists/{id}",
+    "POST /lists/{id}/clone",
+    "GET /items?limit=&offset=&include_snoozed=&ids=&route=",
+    "POST /items?auto_category=",
+    "DELETE /items?list_id=&dry_run=",
+    "GET /items/export.md",
+    "GET /items/todos.ics",
+    "GET /items/export.json",
+    "POST /items/import.json",
+    "GET /items/sections",
+    "GET /items/due?before=",
+    "GET /items/progress",
+    "GET /items/expensive?limit=",
+    "GET /items/stale?days=",
+    "POST /items/complete-trip?clear=",
+    "DELETE /items/completed?list_id=&dry_run=",
+    "POST /items/import-text",
+    "POST /items/import-shared",
+    "PUT /items/swap",
+    "POST /items/dedupe?shopped=keep-unshopped|keep-shopped",
+    "POST /items/rebalance",
+    "POST /items/sort-alphabetical?list_id=",
+    "POST /items/diff",
+    "PUT /items/reset",
+    "PUT /items/category",
+    "PUT /items/quantities?reject_zero=",
+    "PUT /items/toggle-by-name?multiple=conflict|toggle-all",
+    "PUT /items/category/{name}/shopped",
+    "GET /items/by-barcode/{code}",
+    "GET /items/{id}",
+    "PUT /items/{id}",
+    "GET /items/{id}/history",
+    "GET /items/{id}/children",
+    "GET /items/{id}/pairs?limit=",
+    "GET /items/{id}/share",
+    "PUT /items/{id}/toggle?cascade=",
+    "PUT /items/{id}/shopped?cascade=",
+    "PUT /items/{id}/cas",
+    "PUT /items/{id}/purchase",
+    "PUT /items/{id}/pin",
+    "PUT /items/{id}/snooze",
+    "PUT /items/{id}/unpin",
+    "PUT /items/{id}/quantity",
+    "PUT /items/{id}/move",
+    "POST /items/{id}/split",
+    "PUT /items/{id}/aisle-position",
+    "PUT /items/{id}/to-top",
+    "PUT /items/{id}/to-bottom",
+];
+
+async fn index() -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "service": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "endpoints": ENDPOINTS,
+    }))
+}
+
+async fn version(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    Ok(HttpResponse::Ok().json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "schema_version": db::schema_version(&conn)?,
+        "build": env!("GIT_COMMIT"),
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateQuery {
+    /// Reject bodies that carry an `id` instead of silently ignoring it.
+    #[serde(default)]
+    strict: bool,
+    /// Guess a category from the name when the body has none, and answer
+    /// with the created item.
+    #[serde(default)]
+    auto_category: bool,
+}
+
+/// Rejects a barcode already given to an item other than `item_id` with
+/// `409 Conflict`. `idx_items_barcode` would refuse it too, but only as a
+/// database error that doesn't say which item has it.
+fn check_barcode(
+    conn: &Connection,
+    item_id: Option<i32>,
+    barcode: Option<&str>,
+) -> Result<(), ApiError> {
+    let Some(barcode) = barcode else {
+        return Ok(());
+    };
+    let owner: Option<i32> = conn
+        .query_row(
+            "SELECT id FROM shopping_items WHERE barcode = ?1 AND id IS NOT ?2",
+            params![barcode, item_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match owner {
+        Some(owner) => Err(ApiError::Conflict(format!(
+            "Barcode {} is already used by item {}",
+            barcode, owner
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `parent_id` names another item on `list_id` that isn't
+/// `item_id` itself or one of its descendants.
+fn check_parent(
+    conn: &Connection,
+    item_id: Option<i32>,
+    parent_id: Option<i32>,
+    list_id: i64,
+) -> Result<(), ApiError> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+    if item_id == Some(parent_id) {
+        return Err(ApiError::BadRequest(
+            "An item cannot be its own parent".to_string(),
+        ));
+    }
+    let parent_list: Option<i64> = conn
+        .query_row(
+            "SELECT list_id FROM shopping_items WHERE id = ?1",
+            [parent_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match parent_list {
+        None => {
+            return Err(ApiError::BadRequest(format!(
+                "Parent item {} does not exist",
+                parent_id
+            )));
+        }
+        Some(parent_list) if parent_list != list_id => {
+            return Err(ApiError::BadRequest(format!(
+                "Parent item {} is on a different list",
+                parent_id
+            )));
+        }
+        Some(_) => {}
+    }
+
+    if let Some(item_id) = item_id
+        && makes_cycle(conn, item_id, parent_id)?
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Item {} is a sub-item of item {}, which would make a cycle",
+            parent_id, item_id
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `item_id` is `parent_id` or one of its ancestors, so nesting it
+/// under `parent_id` would close a loop.
+fn makes_cycle(conn: &Connection, item_id: i32, parent_id: i32) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "WITH RECURSIVE ancestors(id) AS (
+             SELECT ?1
+             UNION
+             SELECT parent_id FROM shopping_items JOIN ancestors USING (id)
+             WHERE parent_id IS NOT NULL
+         )
+         SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = ?2)",
+        params![parent_id, item_id],
+        |row| row.get(0),
+    )
+}
+
+/// Inserts a new item. With a `position`, the item goes in at that 0-based
+/// index of its list; otherwise it is appended.
+fn insert_item(tx: &Transaction, item: &ShoppingItem) -> Result<(), ApiError> {
+    if !list_exists(tx, item.list_id)? {
+        return Err(unknown_list(item.list_id));
+    }
+    check_parent(tx, None, item.parent_id, item.list_id)?;
+    check_barcode(tx, None, item.barcode.as_deref())?;
+
+    let position = match item.position {
+        None => None,
+        Some(index) if index.fract() != 0.0 => {
+            return Err(ApiError::BadRequest(
+                "position must be a whole number".to_string(),
+            ));
+        }
+        Some(index) => match position::at_index(tx, item.list_id, index as i64, None)? {
+            Ok(position) => Some(position),
+            Err(count) => {
+                return Err(ApiError::BadRequest(format!(
+                    "position must be between 0 and {}",
+                    count
+                )));
+            }
+        },
+    };
+
+    tx.execute(
+        "INSERT INTO shopping_items
+             (name, is_shopped, price_cents, category, quantity, pinned, image_url, remind_at,
+              unit, amount_milli, list_id, position, aisle_position, parent_id, snoozed_until,
+              barcode, shopped_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                 CASE WHEN ?2 THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END)",
+        params![
+            item.name,
+            item.is_shopped,
+            item.price_cents,
+            item.category,
+            item.quantity,
+            item.pinned,
+            item.image_url,
+            item.remind_at,
+            item.unit,
+            item.amount_milli,
+            item.list_id,
+            position,
+            item.aisle_position,
+            item.parent_id,
+            item.snoozed_until,
+            item.barcode
+        ],
+    )?;
+    Ok(())
+}
+
+/// Adds an item. `quantity` and `category` fall back to the configured
+/// defaults when the body leaves them out or sets them to null. With `?auto_category=true` a
+/// category guessed from the name comes before the default, and the new
+/// item is returned.
+async fn add_item(
+    query: web::Query<CreateQuery>,
+    body: web::Json<Map<String, Value>>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let defaults = settings::load(&conn)?;
+    let mut body = body.into_inner();
+    // An explicit null asks for the default just like leaving the field out.
+    for field in ["quantity", "category"] {
+        if body.get(field).is_some_and(Value::is_null) {
+            body.remove(field);
+        }
+    }
+    body.entry("quantity")
+        .or_insert_with(|| json!(defaults.default_quantity));
+    if query.auto_category && !body.contains_key("category") {
+        let name = body.get("name").and_then(Value::as_str).unwrap_or_default();
+        let category = data
+            .categorizer
+            .category(name)
+            .map(str::to_string)
+            .or(defaults.default_category.clone());
+        body.insert("category".to_string(), json!(category));
+    }
+    if let Some(category) = defaults.default_category {
+        body.entry("category").or_insert_with(|| json!(category));
+    }
+    let mut item: ShoppingItem = serde_json::from_value(Value::Object(body))
+        .map_err(|e| ApiError::BadRequest(format!("Json deserialize error: {}", e)))?;
+
+    if query.strict && item.id.is_some() {
+        return Err(ApiError::BadRequest(
+            "id must not be set on create".to_string(),
+        ));
+    }
+    validate_item(&mut item).map_err(ApiError::BadRequest)?;
+    data.check_name(&item.name)?;
+
+    let created = with_transaction(&mut conn, |tx| {
+        insert_item(tx, &item)?;
+        if !query.auto_category {
+            return Ok(None);
+        }
+        let created = fetch_item(tx, tx.last_insert_rowid() as i32)?;
+        Ok(created.map(|mut created| {
+            created
+                .category
+                .get_or_insert_with(|| categorize::UNCATEGORIZED.to_string());
+            created
+        }))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Added item '{}'", request_id, item.name
+    );
+    Ok(match created {
+        Some(created) => HttpResponse::Ok().json(created),
+        None => HttpResponse::Ok().finish(),
+    })
+}
+
+fn insert_imported(tx: &Transaction, items: &[import::ImportedItem]) -> rusqlite::Result<usize> {
+    let mut stmt =
+        tx.prepare("INSERT INTO shopping_items (name, is_shopped, quantity) VALUES (?1, 0, ?2)")?;
+    for item in items {
+        stmt.execute(params![item.name, item.quantity])?;
+    }
+    Ok(items.len())
+}
+
+/// Largest import body accepted, counted after decompression so a small
+/// gzip upload can't expand into an unbounded one.
+const MAX_IMPORT_BYTES: usize = 1024 * 1024;
+
+/// Turns a failure reading a request body, including a compressed one that
+/// actix couldn't decode, into a JSON error.
+fn body_error(err: actix_web::Error) -> ApiError {
+    if err.as_response_error().status_code() == actix_web::http::StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::PayloadTooLarge(format!(
+            "Body must be at most {} bytes once decompressed",
+            MAX_IMPORT_BYTES
+        ))
+    } else {
+        // actix's decompression errors are too low-level to show clients.
+        ApiError::BadRequest("Request body could not be read or decompressed".to_string())
+    }
+}
+
+/// Imports a plain-text list. Bodies sent with `Content-Encoding: gzip` (or
+/// another encoding actix supports) are decompressed before parsing.
+async fn import_text(
+    body: Result<String, actix_web::Error>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let body = body.map_err(body_error)?;
+    let items = import::parse_text_list(&body);
+    for item in &items {
+        data.check_name(&item.name)?;
+    }
+    let mut conn = data.db.get()?;
+    let imported = with_transaction(&mut conn, |tx| Ok(insert_imported(tx, &items)?))?;
+    info!(
+        target: logging::API,
+        "[{}] Imported {} items from text", request_id, imported
+    );
+    Ok(HttpResponse::Ok().json(json!({ "imported": imported })))
+}
+
+/// Returns an item's name, quantity and category with a signed token a
+/// recipient can pass to `POST /items/import-shared` to add the same item.
+async fn share_item(
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let conn = data.db.get()?;
+    let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+
+    let shared = share::SharedItem {
+        name: item.name,
+        quantity: item.quantity,
+        category: item.category,
+    };
+    let now = time::OffsetDateTime::now_utc();
+    let mut payload = json!({
+        "name": shared.name,
+        "quantity": shared.quantity,
+        "category": shared.category,
+        "expires_at": timestamp::format(now + share::TOKEN_TTL),
+    });
+    payload["token"] = json!(data.share_signer.token(shared, now));
+    Ok(HttpResponse::Ok().json(payload))
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportSharedRequest {
+    token: String,
+}
+
+/// Adds the item from a `GET /items/{id}/share` token to the default list.
+/// Tampered and expired tokens are refused with 400.
+async fn import_shared(
+    body: web::Json<ImportSharedRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let shared = data
+        .share_signer
+        .verify(&body.token, time::OffsetDateTime::now_utc())
+        .map_err(ApiError::BadRequest)?;
+    let mut item: ShoppingItem = serde_json::from_value(json!({
+        "name": shared.name,
+        "is_shopped": false,
+        "quantity": shared.quantity,
+        "category": shared.category,
+    }))
+    .map_err(|e| ApiError::Internal(format!("Shared item did not deserialize: {}", e)))?;
+    validate_item(&mut item).map_err(ApiError::BadRequest)?;
+    data.check_name(&item.name)?;
+
+    let mut conn = data.db.get()?;
+    let created = with_transaction(&mut conn, |tx| {
+        insert_item(tx, &item)?;
+        Ok(fetch_item(tx, tx.last_insert_rowid() as i32)?)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Imported shared item '{}'", request_id, item.name
+    );
+    Ok(HttpResponse::Ok().json(created))
+}
+
+#[derive(Debug, Deserialize)]
+struct CascadeQuery {
+    /// Give every sub-item the parent's new state as well.
+    #[serde(default)]
+    cascade: bool,
+}
+
+/// Sets the shopped state of all of an item's descendants to its own.
+fn cascade_shopped(tx: &Transaction, item_id: i32) -> rusqlite::Result<usize> {
+    tx.execute(
+        "WITH RECURSIVE descendants(id) AS (
+             SELECT id FROM shopping_items WHERE parent_id = ?1
+             UNION
+             SELECT shopping_items.id FROM shopping_items
+             JOIN descendants ON shopping_items.parent_id = descendants.id
+         ),
+         parent(is_shopped) AS (SELECT is_shopped FROM shopping_items WHERE id = ?1)
+         UPDATE shopping_items
+         SET is_shopped = (SELECT is_shopped FROM parent),
+             shopped_at = CASE WHEN (SELECT is_shopped FROM parent)
+                               THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+         WHERE id IN (SELECT id FROM descendants)
+           AND is_shopped != (SELECT is_shopped FROM parent)",
+        [item_id],
+    )
+}
+
+const X_CLIENT_ID: header::HeaderName = header::HeaderName::from_static("x-client-id");
+
+/// Identifies the sender of a toggle for debouncing: the `X-Client-Id`
+/// header if sent, otherwise the peer's address.
+fn client_key(req: &HttpRequest) -> String {
+    req.headers()
+        .get(X_CLIENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| req.peer_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_default()
+}
+
+async fn update_item_status(
+    req: HttpRequest,
+    item_id: web::Path<i32>,
+    query: web::Query<CascadeQuery>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let mut conn = data.db.get()?;
+
+    // A debounced toggle answers with the state the first one left behind.
+    if let Some(debouncer) = &data.toggle_debounce
+        && !debouncer.allow(item_id, &client_key(&req))
+    {
+        info!(
+            target: logging::API,
+            "[{}] Ignored repeated toggle of item {}", request_id, item_id
+        );
+        let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+        return Ok(HttpResponse::Ok().json(item));
+    }
+
+    with_transaction(&mut conn, |tx| {
+        tx.execute(
+            "UPDATE shopping_items
+             SET is_shopped = 1 - is_shopped,
+                 shopped_at = CASE WHEN is_shopped THEN NULL
+                                   ELSE strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+             WHERE id = ?1",
+            [item_id],
+        )?;
+        if query.cascade {
+            cascade_shopped(tx, item_id)?;
+        }
+        Ok(())
+    })?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Sets `is_shopped` to an explicit value. Unlike the toggle, repeating the
+/// request (or two clients sending it) leaves the item in the same state.
+async fn set_item_shopped(
+    item_id: web::Path<i32>,
+    query: web::Query<CascadeQuery>,
+    body: web::Json<SetShoppedRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let mut conn = data.db.get()?;
+    let item = with_transaction(&mut conn, |tx| {
+        // Items already in the requested state are left untouched.
+        tx.execute(
+            "UPDATE shopping_items
+             SET is_shopped = ?2,
+                 shopped_at = CASE WHEN ?2 THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+             WHERE id = ?1 AND is_shopped != ?2",
+            params![item_id, body.is_shopped],
+        )?;
+        if query.cascade {
+            cascade_shopped(tx, item_id)?;
+        }
+        fetch_item(tx, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Set is_shopped={} on item {}", request_id, body.is_shopped, item_id
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkCategory {
+    ids: Vec<i32>,
+    /// `null` clears the category.
+    category: Option<String>,
+}
+
+/// Most ids accepted by one bulk update.
+const MAX_BULK_IDS: usize = 1000;
+
+/// Sets the same category on several items at once, returning how many
+/// changed. Unknown ids and items already in the category are skipped.
+async fn set_category_bulk(
+    body: web::Json<BulkCategory>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    if body.ids.len() > MAX_BULK_IDS {
+        return Err(ApiError::BadRequest(format!(
+            "ids must have at most {} entries",
+            MAX_BULK_IDS
+        )));
+    }
+    let category = match body.category.as_deref().map(str::trim) {
+        Some("") => {
+            return Err(ApiError::BadRequest(
+                "category must not be empty, use null to clear it".to_string(),
+            ));
+        }
+        category => category,
+    };
+
+    let mut conn = data.db.get()?;
+    let updated = with_transaction(&mut conn, |tx| {
+        let mut stmt = tx.prepare(
+            "UPDATE shopping_items SET category = ?2 WHERE id = ?1 AND category IS NOT ?2",
+        )?;
+        let mut updated = 0;
+        for id in &body.ids {
+            updated += stmt.execute(params![id, category])?;
+        }
+        Ok(updated)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Set category {:?} on {} of {} items",
+        request_id,
+        category,
+        updated,
+        body.ids.len()
+    );
+    Ok(HttpResponse::Ok().json(json!({ "updated": updated })))
+}
+
+#[derive(Debug, Deserialize)]
+struct CompareAndSwap {
+    expected: bool,
+    new: bool,
+}
+
+/// Sets `is_shopped` to `new` only if it is still `expected`, so concurrent
+/// clients can't overwrite each other's changes unknowingly. A failed
+/// precondition is a `409 Conflict` carrying the current item.
+async fn compare_and_swap_shopped(
+    item_id: web::Path<i32>,
+    body: web::Json<CompareAndSwap>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let conn = data.db.get()?;
+    let swapped = conn.execute(
+        "UPDATE shopping_items
+         SET is_shopped = ?3,
+             shopped_at = CASE WHEN is_shopped = ?3 THEN shopped_at
+                               WHEN ?3 THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+         WHERE id = ?1 AND is_shopped = ?2",
+        params![item_id, body.expected, body.new],
+    )?;
+    let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+
+    if swapped == 0 {
+        info!(
+            target: logging::API,
+            "[{}] is_shopped of item {} is not {}, leaving it", request_id, item_id, body.expected
+        );
+        return Ok(HttpResponse::Conflict().json(json!({
+            "error": format!("is_shopped of item {} is not {}", item_id, body.expected),
+            "item": item,
+        })));
+    }
+    info!(
+        target: logging::API,
+        "[{}] Swapped is_shopped of item {} from {} to {}",
+        request_id,
+        item_id,
+        body.expected,
+        body.new
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// What `PUT /items/toggle-by-name` does when several items match.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum MultipleMatches {
+    /// Answer `409 Conflict` listing the candidates.
+    #[default]
+    Conflict,
+    /// Toggle every match.
+    ToggleAll,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToggleByNameQuery {
+    #[serde(default)]
+    multiple: MultipleMatches,
+}
+
+#[derive(Debug, Deserialize)]
+struct ToggleByNameRequest {
+    name: String,
+}
+
+/// Flips the shopped state of the item called `name`, for integrations that
+/// don't know ids. Names are matched like duplicates are, so
+/// case-insensitively unless `DEDUPE_CASE_SENSITIVE` is set. Returns the item,
+/// or an array of items with `?multiple=toggle-all`.
+async fn toggle_by_name(
+    query: web::Query<ToggleByNameQuery>,
+    body: web::Json<ToggleByNameRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let name = body.name.trim();
+    let mut filter = Filter::default();
+    filter.push(
+        format!("trim(name) = ? COLLATE {}", data.name_matching.collation()),
+        name.to_string(),
+    );
+
+    let mut conn = data.db.get()?;
+    // Ambiguous names come back as `Err` with the candidates, untoggled.
+    let toggled = with_transaction(&mut conn, |tx| {
+        let matches = load_items(tx, &filter, "id")?;
+        if matches.is_empty() {
+            return Err(ApiError::NotFound(format!("No item named '{}'", name)));
+        }
+        if matches.len() > 1 && matches!(query.multiple, MultipleMatches::Conflict) {
+            return Ok(Err(matches));
+        }
+
+        let mut toggled = Vec::with_capacity(matches.len());
+        for item in matches {
+            let id = item.id.unwrap_or_default();
+            tx.execute(
+                "UPDATE shopping_items
+                 SET is_shopped = 1 - is_shopped,
+                     shopped_at = CASE WHEN is_shopped THEN NULL
+                                       ELSE strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+                 WHERE id = ?1",
+                [id],
+            )?;
+            toggled.extend(fetch_item(tx, id)?);
+        }
+        Ok(Ok(toggled))
+    })?;
+    let toggled = match toggled {
+        Ok(toggled) => toggled,
+        Err(candidates) => {
+            return Ok(HttpResponse::Conflict().json(json!({
+                "error": format!("{} items are named '{}'", candidates.len(), name),
+                "candidates": candidates,
+            })));
+        }
+    };
+
+    info!(
+        target: logging::API,
+        "[{}] Toggled {} items named '{}'",
+        request_id,
+        toggled.len(),
+        name
+    );
+    Ok(match query.multiple {
+        MultipleMatches::Conflict => HttpResponse::Ok().json(&toggled[0]),
+        MultipleMatches::ToggleAll => HttpResponse::Ok().json(toggled),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct AislePositionRequest {
+    aisle_position: Option<i64>,
+}
+
+/// Sets (or with `null` clears) an item's place within its category.
+async fn set_aisle_position(
+    item_id: web::Path<i32>,
+    body: web::Json<AislePositionRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let conn = data.db.get()?;
+    conn.execute(
+        "UPDATE shopping_items SET aisle_position = ?2 WHERE id = ?1",
+        params![item_id, body.aisle_position],
+    )?;
+    let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+
+    info!(
+        target: logging::API,
+        "[{}] Set aisle_position={:?} on item {}", request_id, body.aisle_position, item_id
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[derive(Debug, Deserialize)]
+struct SnoozeRequest {
+    /// RFC 3339 time to hide the item until.
+    until: Option<String>,
+    /// Or how long to hide it for, from now.
+    duration_secs: Option<u64>,
+}
+
+/// Longest snooze accepted, a year.
+const MAX_SNOOZE_SECS: u64 = 366 * 24 * 60 * 60;
+
+impl SnoozeRequest {
+    /// The stored `snoozed_until`, or `None` to wake the item up.
+    fn snoozed_until(&self, now: time::OffsetDateTime) -> Result<Option<String>, String> {
+        let until = match (&self.until, self.duration_secs) {
+            (None, None) => return Ok(None),
+            (Some(_), Some(_)) => return Err("Send either until or duration_secs".to_string()),
+            (Some(until), None) => timestamp::parse(until)
+                .ok_or_else(|| format!("'{}' is not a valid RFC 3339 timestamp", until))?,
+            (None, Some(secs)) if (1..=MAX_SNOOZE_SECS).contains(&secs) => {
+                now + Duration::from_secs(secs)
+            }
+            (None, Some(_)) => {
+                return Err(format!(
+                    "duration_secs must be between 1 and {}",
+                    MAX_SNOOZE_SECS
+                ));
+            }
+        };
+        if until <= now {
+            return Err("until must be in the future".to_string());
+        }
+        Ok(Some(timestamp::format(until)))
+    }
+}
+
+/// Hides an item from `GET /items` until the given time, after which it
+/// shows up again by itself. A body with neither field wakes it up now.
+async fn snooze_item(
+    item_id: web::Path<i32>,
+    body: web::Json<SnoozeRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let snoozed_until = body
+        .snoozed_until(time::OffsetDateTime::now_utc())
+        .map_err(ApiError::BadRequest)?;
+    let conn = data.db.get()?;
+    conn.execute(
+        "UPDATE shopping_items SET snoozed_until = ?2 WHERE id = ?1",
+        params![item_id, snoozed_until],
+    )?;
+    let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+
+    info!(
+        target: logging::API,
+        "[{}] Snoozed item {} until {:?}", request_id, item_id, snoozed_until
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+fn set_pinned(item_id: i32, pinned: bool, data: &AppState) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    conn.execute(
+        "UPDATE shopping_items SET pinned = ?2 WHERE id = ?1",
+        params![item_id, pinned],
+    )?;
+    let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+    Ok(HttpResponse::Ok().json(item))
+}
+
+async fn pin_item(
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    set_pinned(item_id.into_inner(), true, &data)
+}
+
+async fn unpin_item(
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    set_pinned(item_id.into_inner(), false, &data)
+}
+
+/// Overwrites every client-editable field of an item. Fields missing from the
+/// body fall back to their defaults; `shopped_at` follows `is_shopped` and
+/// the recorded paid price is left alone.
+fn replace(
+    conn: &Connection,
+    item_id: i32,
+    item: &ShoppingItem,
+) -> rusqlite::Result<Option<ShoppingItem>> {
+    let updated = conn.execute(
+        "UPDATE shopping_items
+         SET name = ?2,
+             is_shopped = ?3,
+             price_cents = ?4,
+             category = ?5,
+             quantity = ?6,
+             pinned = ?7,
+             image_url = ?8,
+             remind_at = ?9,
+             unit = ?10,
+             amount_milli = ?11,
+             list_id = ?12,
+             aisle_position = ?13,
+             parent_id = ?14,
+             snoozed_until = ?15,
+             barcode = ?16,
+             shopped_at = CASE WHEN NOT ?3 THEN NULL
+                               WHEN is_shopped THEN shopped_at
+                               ELSE strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+         WHERE id = ?1",
+        params![
+            item_id,
+            item.name,
+            item.is_shopped,
+            item.price_cents,
+            item.category,
+            item.quantity,
+            item.pinned,
+            item.image_url,
+            item.remind_at,
+            item.unit,
+            item.amount_milli,
+            item.list_id,
+            item.aisle_position,
+            item.parent_id,
+            item.snoozed_until,
+            item.barcode
+        ],
+    )?;
+    if updated == 0 {
+        return Ok(None);
+    }
+    fetch_item(conn, item_id)
+}
+
+/// Entity tag for an item, changing whenever the item does since every
+/// update bumps `updated_at`.
+fn item_etag(item: &ShoppingItem) -> header::EntityTag {
+    let id = item.id.unwrap_or_default();
+    let updated_at = item.updated_at.as_deref().unwrap_or_default();
+    header::EntityTag::new_strong(format!("{}-{}", id, updated_at))
+}
+
+/// Returns the item with a scanned barcode, or 404 so the client can offer
+/// to add it.
+async fn get_item_by_barcode(
+    code: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let code = code.into_inner();
+    let conn = data.db.get()?;
+    let item = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM shopping_items WHERE barcode = ?1",
+                ITEM_COLUMNS
+            ),
+            [code.trim()],
+            item_from_row,
+        )
+        .optional()?
+        .ok_or_else(|| ApiError::NotFound(format!("No item with barcode {}", code)))?;
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// Returns a single item, or `304 Not Modified` when the client's
+/// `If-None-Match` still matches it.
+async fn get_item(
+    req: HttpRequest,
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let conn = data.db.get()?;
+    let item = fetch_item(&conn, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+
+    let etag = item_etag(&item);
+    let unchanged = match header::IfNoneMatch::parse(&req) {
+        Ok(header::IfNoneMatch::Any) => true,
+        Ok(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    if unchanged {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+    Ok(HttpResponse::Ok()
+        .insert_header(header::ETag(etag))
+        .json(item))
+}
+
+async fn replace_item(
+    item_id: web::Path<i32>,
+    mut item: web::Json<ShoppingItem>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    validate_item(&mut item).map_err(ApiError::BadRequest)?;
+    data.check_name(&item.name)?;
+
+    let conn = data.db.get()?;
+    if !list_exists(&conn, item.list_id)? {
+        return Err(unknown_list(item.list_id));
+    }
+    check_parent(&conn, Some(item_id), item.parent_id, item.list_id)?;
+    check_barcode(&conn, Some(item_id), item.barcode.as_deref())?;
+
+    let item = replace(&conn, item_id, &item)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+    info!(
+        target: logging::API,
+        "[{}] Replaced item {}", request_id, item_id
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// Unchecks every item so the same list can be shopped again.
+async fn reset_items(
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    let reset = conn.execute(
+        "UPDATE shopping_items SET is_shopped = 0, shopped_at = NULL WHERE is_shopped != 0",
+        [],
+    )?;
+    info!(
+        target: logging::API,
+        "[{}] Reset {} items to unshopped", request_id, reset
+    );
+    Ok(HttpResponse::Ok().json(json!({ "reset": reset })))
+}
+
+#[derive(Debug, Deserialize)]
+struct SetShoppedRequest {
+    is_shopped: bool,
+}
+
+/// An item as the client last saw it. Other fields the client sends, like
+/// `name`, are ignored.
+#[derive(Debug, Deserialize)]
+struct ClientItem {
+    id: i32,
+    updated_at: String,
+}
+
+/// Compares a client's snapshot with the server's list so an offline client
+/// can reconcile: items it has never seen, ids that no longer exist, and
+/// items changed on the server since the client's copy.
+async fn diff_items(
+    snapshot: web::Json<Vec<ClientItem>>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let mut client = std::collections::HashMap::new();
+    for item in snapshot.iter() {
+        let updated_at = timestamp::normalize(&item.updated_at)
+            .map_err(|e| ApiError::BadRequest(format!("updated_at of item {}: {}", item.id, e)))?;
+        client.insert(item.id, updated_at);
+    }
+
+    let conn = data.db.get()?;
+    let order_by = order_by(None).map_err(ApiError::BadRequest)?;
+    let mut new = Vec::new();
+    let mut updated = Vec::new();
+    for item in load_items(&conn, &Filter::default(), order_by)? {
+        match item.id.and_then(|id| client.remove(&id)) {
+            None => new.push(item),
+            Some(seen)
+                if item
+                    .updated_at
+                    .as_deref()
+                    .is_some_and(|at| at > seen.as_str()) =>
+            {
+                updated.push(item)
+            }
+            Some(_) => {}
+        }
+    }
+    // Whatever the server didn't have is gone.
+    let mut deleted: Vec<i32> = client.into_keys().collect();
+    deleted.sort_unstable();
+
+    Ok(HttpResponse::Ok().json(json!({ "new": new, "updated": updated, "deleted": deleted })))
+}
+
+async fn set_category_shopped(
+    category: web::Path<String>,
+    body: web::Json<SetShoppedRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let category = category.into_inner();
+    let conn = data.db.get()?;
+
+    // Items already in the requested state keep their shopped_at.
+    let updated = conn.execute(
+        "UPDATE shopping_items
+         SET is_shopped = ?2,
+             shopped_at = CASE WHEN ?2 THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+         WHERE (category = ?1 OR (?1 IS NULL AND category IS NULL))
+           AND is_shopped != ?2",
+        params![
+            (category != categorize::UNCATEGORIZED).then_some(&category),
+            body.is_shopped
+        ],
+    )?;
+    info!(
+        target: logging::API,
+        "[{}] Set {} items in category '{}' to shopped={}",
+        request_id,
+        updated,
+        category,
+        body.is_shopped
+    );
+    Ok(HttpResponse::Ok().json(json!({ "updated": updated })))
+}
+
+#[derive(Debug, Deserialize)]
+struct PurchaseRequest {
+    price_cents: i64,
+    /// Defaults to the planned quantity.
+    quantity_bought: Option<i32>,
+}
+
+/// Checks an item off and records what was paid for it, returning the
+/// updated item or `None` if it does not exist.
+fn purchase(
+    tx: &Transaction,
+    item_id: i32,
+    price_cents: i64,
+    quantity_bought: Option<i32>,
+) -> rusqlite::Result<Option<ShoppingItem>> {
+    let updated = tx.execute(
+        "UPDATE shopping_items
+         SET is_shopped = 1,
+             shopped_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+             paid_price_cents = ?2,
+             quantity_bought = COALESCE(?3, quantity)
+         WHERE id = ?1",
+        params![item_id, price_cents, quantity_bought],
+    )?;
+    if updated == 0 {
+        return Ok(None);
+    }
+
+    tx.execute(
+        "INSERT INTO purchase_history
+             (item_id, name, price_cents, purchased_at, quantity, category, unit, amount_milli)
+         SELECT id, name, paid_price_cents, shopped_at, quantity, category, unit, amount_milli
+         FROM shopping_items WHERE id = ?1",
+        [item_id],
+    )?;
+    fetch_item(tx, item_id)
+}
+
+async fn purchase_item(
+    item_id: web::Path<i32>,
+    body: web::Json<PurchaseRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    if body.price_cents < 0 {
+        return Err(ApiError::BadRequest(
+            "price_cents must not be negative".to_string(),
+        ));
+    }
+    if body.quantity_bought.is_some_and(|quantity| quantity < 0) {
+        return Err(ApiError::BadRequest(
+            "quantity_bought must not be negative".to_string(),
+        ));
+    }
+
+    let mut conn = data.db.get()?;
+    let item = with_transaction(&mut conn, |tx| {
+        purchase(tx, item_id, body.price_cents, body.quantity_bought)?
+            .ok_or_else(|| ApiError::item_not_found(item_id))
+    })?;
+    info!(
+        target: logging::API,
+        "[{}] Purchased item {} for {} cents", request_id, item_id, body.price_cents
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[derive(Debug, Deserialize)]
+struct ReaddRequest {
+    /// Case-insensitive search for a purchased item's name.
+    name: Option<String>,
+    history_id: Option<i64>,
+}
+
+/// Adds the best-matching purchase back to the list as a fresh, unshopped
+/// item, returning it or `None` if nothing in the history matches. Exact
+/// name matches win over partial ones, then the most recent purchase.
+fn readd_from_history(
+    tx: &Transaction,
+    request: &ReaddRequest,
+) -> Result<Option<ShoppingItem>, ApiError> {
+    let history_id = match (request.history_id, &request.name) {
+        (Some(history_id), _) => tx
+            .query_row(
+                "SELECT id FROM purchase_history WHERE id = ?1",
+                [history_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?,
+        (None, Some(name)) if !name.trim().is_empty() => tx
+            .query_row(
+                "SELECT id FROM purchase_history
+                 WHERE name LIKE ?1 ESCAPE '\\'
+                 ORDER BY name = ?2 COLLATE NOCASE DESC, purchased_at DESC, id DESC
+                 LIMIT 1",
+                params![format!("%{}%", escape_like(name.trim())), name.trim()],
+                |row| row.get(0),
+            )
+            .optional()?,
+        _ => {
+            return Err(ApiError::BadRequest(
+                "Either name or history_id is required".to_string(),
+            ));
+        }
+    };
+
+    let Some(history_id) = history_id else {
+        return Ok(None);
+    };
+    tx.execute(
+        "INSERT INTO shopping_items (name, is_shopped, quantity, category, unit, amount_milli)
+         SELECT name, 0, quantity, category, unit, amount_milli
+         FROM purchase_history WHERE id = ?1",
+        [history_id],
+    )?;
+    Ok(fetch_item(tx, tx.last_insert_rowid() as i32)?)
+}
+
+async fn readd_item(
+    body: web::Json<ReaddRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let item = with_transaction(&mut conn, |tx| {
+        readd_from_history(tx, &body)?
+            .ok_or_else(|| ApiError::NotFound("No matching purchase in history".to_string()))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Re-added '{}' from purchase history", request_id, item.name
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// What a merged group's shopped state becomes when its items disagree.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ShoppedPolicy {
+    /// Still to buy if any duplicate is, so nothing is silently checked off.
+    #[default]
+    KeepUnshopped,
+    /// Shopped if any duplicate is.
+    KeepShopped,
+}
+
+#[derive(Debug, Deserialize)]
+struct DedupeQuery {
+    #[serde(default)]
+    shopped: ShoppedPolicy,
+}
+
+struct DuplicateGroup {
+    keep_id: i32,
+    list_id: i64,
+    name: String,
+    quantity: i64,
+    all_shopped: bool,
+    any_shopped: bool,
+}
+
+/// Merges items on the same list whose trimmed names match under `matching`
+/// into the lowest id, summing quantities. Returns the number of groups
+/// merged and items removed.
+fn dedupe(
+    tx: &Transaction,
+    matching: db::NameMatching,
+    policy: ShoppedPolicy,
+) -> Result<(usize, usize), ApiError> {
+    // The name is looked up by the kept id: with several aggregates, a bare
+    // `name` column could come from any row of the group.
+    let groups = tx
+        .prepare(&format!(
+            "SELECT keep_id, list_id,
+                    (SELECT name FROM shopping_items WHERE id = keep_id),
+                    quantity, all_shopped, any_shopped
+             FROM (SELECT MIN(id) AS keep_id, list_id, SUM(quantity) AS quantity,
+                          MIN(is_shopped) AS all_shopped, MAX(is_shopped) AS any_shopped
+                   FROM shopping_items
+                   GROUP BY list_id, trim(name) COLLATE {}
+                   HAVING COUNT(*) > 1)",
+            matching.collation()
+        ))?
+        .query_map([], |row| {
+            Ok(DuplicateGroup {
+                keep_id: row.get(0)?,
+                list_id: row.get(1)?,
+                name: row.get(2)?,
+                quantity: row.get(3)?,
+                all_shopped: row.get(4)?,
+                any_shopped: row.get(5)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut removed = 0;
+    for group in &groups {
+        let quantity = i32::try_from(group.quantity).map_err(|_| {
+            ApiError::BadRequest(format!(
+                "Merged quantity of '{}' would be too large",
+                group.name
+            ))
+        })?;
+        let is_shopped = match policy {
+            ShoppedPolicy::KeepUnshopped => group.all_shopped,
+            ShoppedPolicy::KeepShopped => group.any_shopped,
+        };
+        tx.execute(
+            &format!(
+                "UPDATE shopping_items
+                 SET quantity = ?2,
+                     is_shopped = ?3,
+                     shopped_at = CASE WHEN NOT ?3 THEN NULL
+                                       WHEN is_shopped THEN shopped_at
+                                       ELSE (SELECT MAX(shopped_at) FROM shopping_items
+                                             WHERE list_id = ?4
+                                               AND trim(name) = trim(?5) COLLATE {})
+                                  END
+                 WHERE id = ?1",
+                matching.collation()
+            ),
+            params![
+                group.keep_id,
+                quantity,
+                is_shopped,
+                group.list_id,
+                group.name
+            ],
+        )?;
+        removed += tx.execute(
+            &format!(
+                "DELETE FROM shopping_items
+                 WHERE id != ?1 AND list_id = ?2 AND trim(name) = trim(?3) COLLATE {}",
+                matching.collation()
+            ),
+            params![group.keep_id, group.list_id, group.name],
+        )?;
+    }
+    Ok((groups.len(), removed))
+}
+
+async fn dedupe_items(
+    query: web::Query<DedupeQuery>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let (merged_groups, removed) = with_transaction(&mut conn, |tx| {
+        dedupe(tx, data.name_matching, query.shopped)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Merged {} groups of duplicates, removing {} items",
+        request_id,
+        merged_groups,
+        removed
+    );
+    Ok(HttpResponse::Ok().json(json!({ "merged_groups": merged_groups, "removed": removed })))
+}
+
+#[derive(Debug, Deserialize)]
+struct QuantityDelta {
+    delta: i64,
+}
+
+/// Applies a quantity change, rejecting deltas outside `i32` and results
+/// below 1 or too large to store.
+fn apply_quantity_delta(quantity: i32, delta: i64) -> Result<i32, String> {
+    let delta = i32::try_from(delta)
+        .map_err(|_| format!("delta must be between {} and {}", i32::MIN, i32::MAX))?;
+    match quantity.checked_add(delta) {
+        Some(quantity) if quantity >= 1 => Ok(quantity),
+        Some(_) => Err(format!(
+            "delta {} would bring quantity {} below 1",
+            delta, quantity
+        )),
+        None => Err(format!(
+            "delta {} would overflow quantity {}",
+            delta, quantity
+        )),
+    }
+}
+
+/// Increments or decrements an item's quantity by `delta`.
+async fn change_quantity(
+    item_id: web::Path<i32>,
+    body: web::Json<QuantityDelta>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let mut conn = data.db.get()?;
+    let (quantity, item) = with_transaction(&mut conn, |tx| {
+        let quantity: i32 = tx
+            .query_row(
+                "SELECT quantity FROM shopping_items WHERE id = ?1",
+                [item_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| ApiError::item_not_found(item_id))?;
+        let quantity = apply_quantity_delta(quantity, body.delta).map_err(ApiError::BadRequest)?;
+        tx.execute(
+            "UPDATE shopping_items SET quantity = ?2 WHERE id = ?1",
+            params![item_id, quantity],
+        )?;
+        let item = fetch_item(tx, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))?;
+        Ok((quantity, item))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Changed quantity of item {} by {} to {}", request_id, item_id, body.delta, quantity
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[derive(Debug, Deserialize)]
+struct QuantitiesQuery {
+    /// Refuse quantities of 0 instead of deleting those items.
+    #[serde(default)]
+    reject_zero: bool,
+}
+
+/// Sets the quantities of several items from an `{ "id": quantity }` map in
+/// one transaction, deleting items set to 0 unless `?reject_zero=true`.
+/// Every quantity and id is checked before anything is written. Returns the
+/// items that were kept, by id.
+async fn set_quantities(
+    query: web::Query<QuantitiesQuery>,
+    body: web::Json<std::collections::BTreeMap<i32, i64>>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    if body.len() > MAX_BULK_IDS {
+        return Err(ApiError::BadRequest(format!(
+            "At most {} quantities can be set at once",
+            MAX_BULK_IDS
+        )));
+    }
+    let min = if query.reject_zero { 1 } else { 0 };
+    for (id, &quantity) in body.iter() {
+        if quantity < min || quantity > i64::from(i32::MAX) {
+            return Err(ApiError::BadRequest(format!(
+                "quantity of item {} must be between {} and {}",
+                id,
+                min,
+                i32::MAX
+            )));
+        }
+    }
+
+    let mut conn = data.db.get()?;
+    let (items, deleted) = with_transaction(&mut conn, |tx| {
+        for &id in body.keys() {
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM shopping_items WHERE id = ?1)",
+                [id],
+                |row| row.get(0),
+            )?;
+            if !exists {
+                return Err(ApiError::item_not_found(id));
+            }
+        }
+        let mut items = Vec::new();
+        let mut deleted = 0;
+        for (&id, &quantity) in body.iter() {
+            if quantity == 0 {
+                deleted += tx.execute("DELETE FROM shopping_items WHERE id = ?1", [id])?;
+                continue;
+            }
+            tx.execute(
+                "UPDATE shopping_items SET quantity = ?2 WHERE id = ?1 AND quantity != ?2",
+                params![id, quantity],
+            )?;
+            items.extend(fetch_item(tx, id)?);
+        }
+        Ok((items, deleted))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Set quantities of {} items, deleted {}",
+        request_id,
+        items.len(),
+        deleted
+    );
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[derive(Debug, Deserialize)]
+struct SplitRequest {
+    names: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SplitQuery {
+    #[serde(default)]
+    keep_original: bool,
+}
+
+/// Most items a single split may create.
+const MAX_SPLIT_NAMES: usize = 100;
+
+/// Replaces an item with one new item per name, e.g. "groceries" with
+/// "milk", "bread" and "eggs". The new items take the original's list,
+/// category and aisle position and are appended to the list. The original
+/// is deleted unless `?keep_original=true`.
+async fn split_item(
+    item_id: web::Path<i32>,
+    query: web::Query<SplitQuery>,
+    body: web::Json<SplitRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let names: Vec<&str> = body.names.iter().map(|name| name.trim()).collect();
+    if names.is_empty() {
+        return Err(ApiError::BadRequest("names must not be empty".to_string()));
+    }
+    if names.len() > MAX_SPLIT_NAMES {
+        return Err(ApiError::BadRequest(format!(
+            "names must have at most {} entries",
+            MAX_SPLIT_NAMES
+        )));
+    }
+    for name in &names {
+        if name.is_empty() {
+            return Err(ApiError::BadRequest(
+                "names must not contain empty names".to_string(),
+            ));
+        }
+        data.check_name(name)?;
+    }
+
+    let mut conn = data.db.get()?;
+    let created = with_transaction(&mut conn, |tx| {
+        if fetch_item(tx, item_id)?.is_none() {
+            return Err(ApiError::item_not_found(item_id));
+        }
+        let mut created = Vec::with_capacity(names.len());
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO shopping_items (name, is_shopped, category, list_id, aisle_position)
+                 SELECT ?1, 0, category, list_id, aisle_position FROM shopping_items WHERE id = ?2",
+            )?;
+            for name in &names {
+                insert.execute(params![name, item_id])?;
+                let id = tx.last_insert_rowid() as i32;
+                created.push(fetch_item(tx, id)?.ok_or_else(|| ApiError::item_not_found(id))?);
+            }
+        }
+        if !query.keep_original {
+            tx.execute("DELETE FROM shopping_items WHERE id = ?1", [item_id])?;
+        }
+        Ok(created)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Split item {} into {} items",
+        request_id,
+        item_id,
+        created.len()
+    );
+    Ok(HttpResponse::Ok().json(created))
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveRequest {
+    /// 0-based index among the other items of the list.
+    index: i64,
+}
+
+/// Moves an item to `index` within its list, writing only that item.
+async fn move_item_to(
+    item_id: web::Path<i32>,
+    body: web::Json<MoveRequest>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let item_id = item_id.into_inner();
+    let mut conn = data.db.get()?;
+    let item = with_transaction(&mut conn, |tx| {
+        let list_id: i64 = tx
+            .query_row(
+                "SELECT list_id FROM shopping_items WHERE id = ?1",
+                [item_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| ApiError::item_not_found(item_id))?;
+        let position =
+            position::at_index(tx, list_id, body.index, Some(item_id))?.map_err(|count| {
+                ApiError::BadRequest(format!("index must be between 0 and {}", count))
+            })?;
+        tx.execute(
+            "UPDATE shopping_items SET position = ?2 WHERE id = ?1",
+            params![item_id, position],
+        )?;
+        fetch_item(tx, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Moved item {} to index {}", request_id, item_id, body.index
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// Renumbers every list's positions to whole numbers, for when repeated
+/// moves have left them crowded.
+async fn rebalance_positions(
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let rebalanced = with_transaction(&mut conn, |tx| {
+        let list_ids = tx
+            .prepare("SELECT id FROM lists")?
+            .query_map([], |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut rebalanced = 0;
+        for list_id in list_ids {
+            rebalanced += position::rebalance(tx, list_id)?;
+        }
+        Ok(rebalanced)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Rebalanced {} item positions", request_id, rebalanced
+    );
+    Ok(HttpResponse::Ok().json(json!({ "rebalanced": rebalanced })))
+}
+
+#[derive(Debug, Deserialize)]
+struct SortAlphabeticalQuery {
+    /// Only sort this list; every list when unset.
+    list_id: Option<i64>,
+}
+
+/// Stores items in alphabetical order by renumbering their positions, so
+/// the default order follows the names until items are moved again.
+/// Returns the lists that were sorted in their new order.
+async fn sort_alphabetical(
+    query: web::Query<SortAlphabeticalQuery>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let (sorted, items) = with_transaction(&mut conn, |tx| {
+        let list_ids = match query.list_id {
+            Some(list_id) => {
+                if !list_exists(tx, list_id)? {
+                    return Err(list_not_found(list_id));
+                }
+                vec![list_id]
+            }
+            None => tx
+                .prepare("SELECT id FROM lists")?
+                .query_map([], |row| row.get::<_, i64>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?,
+        };
+        let mut sorted = 0;
+        for &list_id in &list_ids {
+            sorted += position::sort_by_name(tx, list_id)?;
+        }
+        let mut filter = Filter::default();
+        if let Some(list_id) = query.list_id {
+            filter.push("list_id = ?", list_id);
+        }
+        let items = load_items(tx, &filter, DEFAULT_ORDER)?;
+        Ok((sorted, items))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Sorted {} items alphabetically", request_id, sorted
+    );
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[derive(Clone, Copy)]
+enum End {
+    Top,
+    Bottom,
+}
+
+/// Moves an item before (or after) every other item on its list.
+fn move_to_end(tx: &Transaction, item_id: i32, end: End) -> rusqlite::Result<usize> {
+    let target = match end {
+        End::Top => "MIN(position) - 1",
+        End::Bottom => "MAX(position) + 1",
+    };
+    tx.execute(
+        &format!(
+            "UPDATE shopping_items
+             SET position = (SELECT {} FROM shopping_items AS other
+                             WHERE other.list_id = shopping_items.list_id)
+             WHERE id = ?1",
+            target
+        ),
+        [item_id],
+    )
+}
+
+fn move_item(
+    item_id: i32,
+    end: End,
+    data: &AppState,
+    request_id: &RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let item = with_transaction(&mut conn, |tx| {
+        move_to_end(tx, item_id, end)?;
+        fetch_item(tx, item_id)?.ok_or_else(|| ApiError::item_not_found(item_id))
+    })?;
+
+    let end = match end {
+        End::Top => "top",
+        End::Bottom => "bottom",
+    };
+    info!(
+        target: logging::API,
+        "[{}] Moved item {} to the {}", request_id, item_id, end
+    );
+    Ok(HttpResponse::Ok().json(item))
+}
+
+async fn move_item_to_top(
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    move_item(item_id.into_inner(), End::Top, &data, &request_id)
+}
+
+async fn move_item_to_bottom(
+    item_id: web::Path<i32>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    move_item(item_id.into_inner(), End::Bottom, &data, &request_id)
+}
+
+async fn swap_items(
+    items: web::Json<(i32, i32)>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let (id1, id2) = items.into_inner(); // Extract the tuple from web::Json
+    if id1 == id2 {
+        return Err(ApiError::BadRequest(
+            "Cannot swap an item with itself".to_string(),
+        ));
+    }
+
+    let mut conn = data.db.get()?;
+    with_transaction(&mut conn, |tx| {
+        let placement_of = |id| {
+            tx.query_row(
+                "SELECT list_id, position FROM shopping_items WHERE id = ?1",
+                [id],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<f64>>(1)?)),
+            )
+            .optional()
+        };
+        let (Some((list1, position1)), Some((list2, position2))) =
+            (placement_of(id1)?, placement_of(id2)?)
+        else {
+            info!(
+                target: logging::API,
+                "[{}] Rejected swap of unknown items {} and {}", request_id, id1, id2
+            );
+            return Err(ApiError::BadRequest(format!(
+                "Items {} and {} must both exist",
+                id1, id2
+            )));
+        };
+        // Positions only order items within a list, so swapping them across
+        // lists would scramble both.
+        if list1 != list2 {
+            info!(
+                target: logging::API,
+                "[{}] Rejected swap of items {} and {} from lists {} and {}",
+                request_id,
+                id1,
+                id2,
+                list1,
+                list2
+            );
+            return Err(ApiError::BadRequest(format!(
+                "Items {} and {} are on different lists",
+                id1, id2
+            )));
+        }
+
+        // Swap the positions
+        {
+            let mut stmt = tx.prepare("UPDATE shopping_items SET position = ?2 WHERE id = ?1")?;
+            stmt.execute(params![id1, position2])?;
+            stmt.execute(params![id2, position1])?;
+        }
+        Ok(())
+    })?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct List {
+    id: Option<i64>,
+    name: String,
+    /// Swatch shown next to the list, as `#RRGGBB`.
+    #[serde(default)]
+    color: Option<String>,
+    /// Short label such as an emoji.
+    #[serde(default)]
+    icon: Option<String>,
+}
+
+/// Longest `icon` accepted, in characters.
+const MAX_LIST_ICON_LEN: usize = 16;
+
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7 && color.starts_with('#') && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl List {
+    /// Checks the client-supplied fields, trimming the name and icon.
+    fn validate(&mut self) -> Result<(), String> {
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err("name must not be empty".to_string());
+        }
+        if let Some(color) = &self.color
+            && !is_hex_color(color)
+        {
+            return Err("color must be a hex color like #1E90FF".to_string());
+        }
+        if let Some(icon) = &self.icon {
+            let icon = icon.trim();
+            if icon.is_empty() || icon.chars().count() > MAX_LIST_ICON_LEN {
+                return Err(format!(
+                    "icon must be between 1 and {} characters",
+                    MAX_LIST_ICON_LEN
+                ));
+            }
+            self.icon = Some(icon.to_string());
+        }
+        Ok(())
+    }
+}
+
+fn list_from_row(row: &Row) -> rusqlite::Result<List> {
+    Ok(List {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        color: row.get(2)?,
+        icon: row.get(3)?,
+    })
+}
+
+fn list_not_found(list_id: i64) -> ApiError {
+    ApiError::NotFound(format!("List {} not found", list_id))
+}
+
+async fn get_lists(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    let mut stmt = conn.prepare("SELECT id, name, color, icon FROM lists ORDER BY id")?;
+    let lists = stmt
+        .query_map([], list_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+async fn create_list(
+    list: web::Json<List>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut list = list.into_inner();
+    list.validate().map_err(ApiError::BadRequest)?;
+
+    let conn = data.db.get()?;
+    conn.execute(
+        "INSERT INTO lists (name, color, icon) VALUES (?1, ?2, ?3)",
+        params![list.name, list.color, list.icon],
+    )?;
+    list.id = Some(conn.last_insert_rowid());
+    info!(
+        target: logging::API,
+        "[{}] Created list '{}'", request_id, list.name
+    );
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// Renames a list and replaces its color and icon.
+async fn update_list(
+    list_id: web::Path<i64>,
+    list: web::Json<List>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let list_id = list_id.into_inner();
+    let mut list = list.into_inner();
+    list.validate().map_err(ApiError::BadRequest)?;
+
+    let conn = data.db.get()?;
+    let updated = conn.execute(
+        "UPDATE lists SET name = ?2, color = ?3, icon = ?4 WHERE id = ?1",
+        params![list_id, list.name, list.color, list.icon],
+    )?;
+    if updated == 0 {
+        return Err(list_not_found(list_id));
+    }
+    list.id = Some(list_id);
+    info!(
+        target: logging::API,
+        "[{}] Updated list {}", request_id, list_id
+    );
+    Ok(HttpResponse::Ok().json(list))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CloneListRequest {
+    /// Defaults to the source list's name.
+    name: Option<String>,
+}
+
+/// `name`, or the first of "name (copy)", "name (copy 2)", ... that no
+/// other list uses yet.
+fn unused_list_name(tx: &Transaction, name: &str) -> rusqlite::Result<String> {
+    let mut stmt = tx.prepare("SELECT EXISTS (SELECT 1 FROM lists WHERE name = ?1)")?;
+    let mut candidate = name.to_string();
+    let mut copy = 1;
+    while stmt.query_row([&candidate], |row| row.get::<_, bool>(0))? {
+        candidate = match copy {
+            1 => format!("{} (copy)", name),
+            n => format!("{} (copy {})", name, n),
+        };
+        copy += 1;
+    }
+    Ok(candidate)
+}
+
+/// Copies a list and all its items, unshopped, in the same order and with
+/// sub-items under the copy of their parent, for "same as last week". The
+/// body is optional.
+async fn clone_list(
+    list_id: web::Path<i64>,
+    body: web::Bytes,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let list_id = list_id.into_inner();
+    let request: CloneListRequest = if body.is_empty() {
+        CloneListRequest::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| ApiError::BadRequest(format!("Json deserialize error: {}", e)))?
+    };
+    let name = request.name.as_deref().map(str::trim);
+    if name == Some("") {
+        return Err(ApiError::BadRequest("name must not be empty".to_string()));
+    }
+
+    let mut conn = data.db.get()?;
+    let (name, clone_id, item_count) = with_transaction(&mut conn, |tx| {
+        let source = tx
+            .query_row(
+                "SELECT id, name, color, icon FROM lists WHERE id = ?1",
+                [list_id],
+                list_from_row,
+            )
+            .optional()?
+            .ok_or_else(|| list_not_found(list_id))?;
+        let name = unused_list_name(tx, name.unwrap_or(&source.name))?;
+        tx.execute(
+            "INSERT INTO lists (name, color, icon) VALUES (?1, ?2, ?3)",
+            params![name, source.color, source.icon],
+        )?;
+        let clone_id = tx.last_insert_rowid();
+        let sources = tx
+            .prepare(
+                "SELECT id, parent_id FROM shopping_items WHERE list_id = ?1
+                 ORDER BY position, id",
+            )?
+            .query_map([list_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Copies get new ids, so sub-items are pointed at their parent's
+        // copy once every item has one.
+        let mut copy = tx.prepare(
+            "INSERT INTO shopping_items
+                 (name, is_shopped, price_cents, category, quantity, pinned, image_url, remind_at,
+                  unit, amount_milli, list_id, position, aisle_position)
+             SELECT name, 0, price_cents, category, quantity, pinned, image_url, remind_at,
+                    unit, amount_milli, ?2, position, aisle_position
+             FROM shopping_items WHERE id = ?1",
+        )?;
+        let mut copies = std::collections::HashMap::with_capacity(sources.len());
+        for &(id, _) in &sources {
+            copy.execute(params![id, clone_id])?;
+            copies.insert(id, tx.last_insert_rowid());
+        }
+        let mut nest = tx.prepare("UPDATE shopping_items SET parent_id = ?2 WHERE id = ?1")?;
+        for (id, parent_id) in &sources {
+            if let Some(parent_copy) = parent_id.and_then(|parent_id| copies.get(&parent_id)) {
+                nest.execute(params![copies[id], parent_copy])?;
+            }
+        }
+        Ok((name, clone_id, sources.len()))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Cloned list {} into list {} '{}' with {} items",
+        request_id,
+        list_id,
+        clone_id,
+        name,
+        item_count
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "id": clone_id,
+        "name": name,
+        "item_count": item_count,
+    })))
+}
+
+#[derive(Debug, Serialize)]
+struct ListSummary {
+    list_id: i64,
+    name: String,
+    total: i64,
+    remaining: i64,
+}
+
+/// Item counts for every list, including empty ones, in a single query.
+async fn get_lists_summary(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT lists.id, lists.name, COUNT(shopping_items.id),
+                COALESCE(SUM(NOT shopping_items.is_shopped), 0)
+         FROM lists
+         LEFT JOIN shopping_items ON shopping_items.list_id = lists.id
+         GROUP BY lists.id
+         ORDER BY lists.id",
+    )?;
+    let summary = stmt
+        .query_map([], |row| {
+            Ok(ListSummary {
+                list_id: row.get(0)?,
+                name: row.get(1)?,
+                total: row.get(2)?,
+                remaining: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+#[derive(Debug, Deserialize)]
+struct CompleteTripQuery {
+    /// Only archive this list's items.
+    list_id: Option<i64>,
+    /// Delete the archived items from the list afterwards.
+    #[serde(default)]
+    clear: bool,
+}
+
+/// Archives the shopped items as a trip, optionally clearing them from the
+/// list in the same transaction.
+async fn complete_trip(
+    query: web::Query<CompleteTripQuery>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let trip = with_transaction(&mut conn, |tx| {
+        let trip = trips::create(tx, query.list_id)?
+            .ok_or_else(|| ApiError::BadRequest("No shopped items to archive".to_string()))?;
+        if query.clear {
+            tx.execute(
+                &format!("DELETE FROM shopping_items WHERE {}", COMPLETED_ITEMS),
+                [query.list_id],
+            )?;
+        }
+        Ok(trip)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Completed trip {} with {} items{}",
+        request_id,
+        trip.id,
+        trip.items.len(),
+        if query.clear { ", cleared them" } else { "" }
+    );
+    Ok(HttpResponse::Ok().json(trip))
+}
+
+#[derive(Debug, Deserialize)]
+struct ClearQuery {
+    /// Only clear this list's items.
+    list_id: Option<i64>,
+    /// Count the items that would be deleted instead of deleting them.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Items cleared by `DELETE /items/completed`, `?1` being the list.
+const COMPLETED_ITEMS: &str = "is_shopped AND (?1 IS NULL OR list_id = ?1)";
+/// Items cleared by `DELETE /items`, `?1` being the list.
+const ALL_ITEMS: &str = "?1 IS NULL OR list_id = ?1";
+
+/// Deletes the items matching `condition`, or only counts them on a dry run
+/// so a client can confirm the impact first.
+fn clear_items(
+    condition: &str,
+    query: &ClearQuery,
+    data: &AppState,
+    request_id: &RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    if query.dry_run {
+        let would_delete: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM shopping_items WHERE {}", condition),
+            [query.list_id],
+            |row| row.get(0),
+        )?;
+        return Ok(HttpResponse::Ok().json(json!({ "would_delete": would_delete })));
+    }
+
+    let deleted = conn.execute(
+        &format!("DELETE FROM shopping_items WHERE {}", condition),
+        [query.list_id],
+    )?;
+    info!(
+        target: logging::API,
+        "[{}] Cleared {} items", request_id, deleted
+    );
+    Ok(HttpResponse::Ok().json(json!({ "deleted": deleted })))
+}
+
+/// Deletes the shopped items without archiving them as a trip.
+async fn clear_completed(
+    query: web::Query<ClearQuery>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    clear_items(COMPLETED_ITEMS, &query, &data, &request_id)
+}
+
+/// Deletes every item, or every item of `?list_id=`.
+async fn clear_all(
+    query: web::Query<ClearQuery>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    clear_items(ALL_ITEMS, &query, &data, &request_id)
+}
+
+/// The categories of a store in walking order, as used by
+/// `GET /items?route=`.
+async fn get_store_layout(
+    store: web::Path<String>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let store = store.into_inner();
+    let conn = data.db.get()?;
+    let categories = stores::layout(&conn, &store)?;
+    if categories.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "Store '{}' has no layout",
+            store
+        )));
+    }
+    Ok(HttpResponse::Ok().json(json!({ "store": store, "categories": categories })))
+}
+
+#[derive(Debug, Deserialize)]
+struct StoreLayout {
+    /// Categories in the order they are passed walking through the store.
+    categories: Vec<String>,
+}
+
+/// Replaces a store's layout; an empty list of categories removes it.
+async fn set_store_layout(
+    store: web::Path<String>,
+    body: web::Json<StoreLayout>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let store = store.into_inner();
+    let categories = stores::validate_layout(&body.categories).map_err(ApiError::BadRequest)?;
+    let mut conn = data.db.get()?;
+    with_transaction(&mut conn, |tx| {
+        Ok(stores::set_layout(tx, &store, &categories)?)
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Set layout of store '{}' to {} categories",
+        request_id,
+        store,
+        categories.len()
+    );
+    Ok(HttpResponse::Ok().json(json!({ "store": store, "categories": categories })))
+}
+
+#[derive(Debug, Deserialize)]
+struct TripsQuery {
+    limit: Option<u32>,
+}
+
+const DEFAULT_TRIPS_LIMIT: u32 = 20;
+const MAX_TRIPS_LIMIT: u32 = 100;
+
+/// Past trips, newest first.
+async fn get_trips(
+    query: web::Query<TripsQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_TRIPS_LIMIT)
+        .min(MAX_TRIPS_LIMIT);
+    let conn = data.db.get()?;
+    Ok(HttpResponse::Ok().json(trips::recent(&conn, limit)?))
+}
+
+#[derive(Debug, Deserialize)]
+struct ProgressQuery {
+    /// Only count this list's items.
+    list_id: Option<i64>,
+}
+
+/// Share of items already shopped, rounded down so 100 means everything
+/// is in the cart. An empty list counts as 0% complete.
+fn percent_complete(shopped: i64, total: i64) -> i64 {
+    if total == 0 { 0 } else { shopped * 100 / total }
+}
+
+/// How far along the shopping trip is, for progress bars.
+async fn get_progress(
+    query: web::Query<ProgressQuery>,
+    data: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    let (shopped, total): (i64, i64) = conn.query_row(
+        "SELECT COALESCE(SUM(is_shopped), 0), COUNT(*) FROM shopping_items
+         WHERE ?1 IS NULL OR list_id = ?1",
+        [query.list_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok(HttpResponse::Ok().json(json!({
+        "percent_complete": percent_complete(shopped, total),
+        "shopped": shopped,
+        "total": total,
+    })))
+}
+
+/// Size of the database file in bytes.
+fn db_file_size() -> Result<u64, ApiError> {
+    std::fs::metadata(DB_PATH)
+        .map(|metadata| metadata.len())
+        .map_err(|e| ApiError::Internal(format!("failed to stat {}: {}", DB_PATH, e)))
+}
+
+async fn get_settings(data: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let conn = data.db.get()?;
+    Ok(HttpResponse::Ok().json(settings::load(&conn)?))
+}
+
+async fn put_settings(
+    body: web::Json<settings::Settings>,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut new_settings = body.into_inner();
+    new_settings.validate().map_err(ApiError::BadRequest)?;
+
+    let conn = data.db.get()?;
+    settings::save(&conn, &new_settings)?;
+    info!(
+        target: logging::API,
+        "[{}] Updated settings: {:?}", request_id, new_settings
+    );
+    Ok(HttpResponse::Ok().json(new_settings))
+}
+
+/// Reports pool usage so operators can spot leaked connections or
+/// exhaustion. SQLite has no server-side sessions to kill, so this is
+/// read-only.
+async fn admin_connections(_: Admin, data: web::Data<AppState>) -> impl Responder {
+    let state = data.db.state();
+    HttpResponse::Ok().json(json!({
+        "max_size": state.max_size,
+        "connections": state.connections,
+        "idle": state.idle_connections,
+        "in_use": state.connections - state.idle_connections,
+        "checkouts": state.checkouts,
+        "timeouts": state.timeouts,
+    }))
+}
+
+/// Rebuilds the database file to reclaim space left behind by deletes.
+///
+/// `VACUUM` can't run inside a transaction and needs the database to
+/// itself, so it runs on a dedicated connection rather than a pooled one,
+/// and other writers may briefly block (up to their busy timeout) while it
+/// runs.
+async fn vacuum(_: Admin, request_id: RequestId) -> Result<HttpResponse, ApiError> {
+    let size_before = db_file_size()?;
+    let conn = db::open(DB_PATH)?;
+    conn.busy_timeout(Duration::from_secs(30))?;
+    conn.execute_batch("VACUUM")?;
+    drop(conn);
+    let size_after = db_file_size()?;
+
+    info!(
+        target: logging::API,
+        "[{}] Vacuumed database, reclaimed {} bytes ({} -> {})",
+        request_id,
+        size_before.saturating_sub(size_after),
+        size_before,
+        size_after
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "size_before": size_before,
+        "size_after": size_after,
+    })))
+}
+
+/// Rewrites `is_shopped` values left as strings by early versions
+/// ("true", "0", ...) as the integers 0 and 1. Values it doesn't recognize
+/// are reported and left for a human to look at. Running it again changes
+/// nothing.
+async fn repair(
+    _: Admin,
+    data: web::Data<AppState>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut conn = data.db.get()?;
+    let (fixed, unrecognized) = with_transaction(&mut conn, |tx| {
+        let fixed = tx.execute(
+            "UPDATE shopping_items
+             SET is_shopped = lower(trim(is_shopped)) IN ('true', '1')
+             WHERE typeof(is_shopped) != 'integer'
+               AND lower(trim(is_shopped)) IN ('true', 'false', '1', '0')",
+            [],
+        )?;
+        let unrecognized: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM shopping_items
+             WHERE typeof(is_shopped) != 'integer' OR is_shopped NOT IN (0, 1)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok((fixed, unrecognized))
+    })?;
+
+    info!(
+        target: logging::API,
+        "[{}] Repaired is_shopped on {} items, {} unrecognized", request_id, fixed, unrecognized
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "fixed": fixed,
+        "unrecognized": unrecognized,
+    })))
+}
+
+/// Logs rejected JSON bodies together with the request id before actix turns
+/// them into a `400 Bad Request`, or `415 Unsupported Media Type` when the
+/// body wasn't sent as JSON at all.
+fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    req: &HttpRequest,
+) -> actix_web::Error {
+    error!(
+        target: logging::API,
+        "[{}] Rejected JSON body for {} {}: {}",
+        request_id::of(req),
+        req.method(),
+        req.path(),
+        err
+    );
+    match err {
+        actix_web::error::JsonPayloadError::ContentType => {
+            ApiError::UnsupportedMediaType("Content-Type must be application/json".to_string())
+                .into()
+        }
+        err => ApiError::BadRequest(err.to_string()).into(),
+    }
+}
+
+const DB_PATH: &str = "shopping_list.db";
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    logging::init();
+    let slow_query_ms = env::var("SLOW_QUERY_MS")
+        .map(|ms| ms.parse().expect("SLOW_QUERY_MS must be an integer"))
+        .unwrap_or(100);
+    db::set_slow_query_threshold(Duration::from_millis(slow_query_ms));
+
+    let mut conn = db::open(DB_PATH).unwrap();
+    db::init(&mut conn).unwrap();
+    db::check_indexes(&conn).unwrap();
+    drop(conn);
+
+    let workers = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    // Handlers hold at most one connection at a time, so one per worker
+    // thread is enough unless overridden.
+    let pool_size = env::var("DB_POOL_SIZE")
+        .map(|size| {
+            size.parse::<NonZeroU32>()
+                .expect("DB_POOL_SIZE must be a positive integer")
+                .get()
+        })
+        .unwrap_or(workers as u32);
+    let pool_timeout = env::var("DB_POOL_TIMEOUT_SECS")
+        .map(|secs| {
+            secs.parse()
+                .expect("DB_POOL_TIMEOUT_SECS must be an integer")
+        })
+        .unwrap_or(5);
+    info!(
+        target: logging::SERVER,
+        "Using a pool of {} database connections with a {}s timeout", pool_size, pool_timeout
+    );
+
+    let name_matching = match env::var("DEDUPE_CASE_SENSITIVE").as_deref() {
+        Ok("true" | "1") => db::NameMatching::CaseSensitive,
+        Ok("false" | "0") | Err(_) => db::NameMatching::CaseInsensitive,
+        Ok(other) => panic!(
+            "DEDUPE_CASE_SENSITIVE must be true or false, got {:?}",
+            other
+        ),
+    };
+
+    let request_timeout = env::var("REQUEST_TIMEOUT_MS")
+        .map(|ms| {
+            ms.parse()
+                .ok()
+                .filter(|&ms| ms > 0)
+                .expect("REQUEST_TIMEOUT_MS must be a positive integer")
+        })
+        .unwrap_or(10_000);
+    let request_timeout = Duration::from_millis(request_timeout);
+
+    let read_only = matches!(env::var("READ_ONLY").as_deref(), Ok("true" | "1"));
+    if read_only {
+        warn!(
+            target: logging::SERVER,
+            "Read-only mode is active, write requests will be rejected"
+        );
+    }
+
+    let word_filter = env::var("WORD_FILTER_PATH").ok().map(|path| {
+        let filter = word_filter::WordFilter::load(&path)
+            .unwrap_or_else(|e| panic!("Failed to read WORD_FILTER_PATH {}: {}", path, e));
+        info!(
+            target: logging::SERVER,
+            "Loaded {} words to filter from {}",
+            filter.len(),
+            path
+        );
+        filter
+    });
+
+    let toggle_debounce = env::var("TOGGLE_DEBOUNCE_MS").ok().map(|ms| {
+        let ms = ms
+            .parse()
+            .ok()
+            .filter(|&ms| ms > 0)
+            .expect("TOGGLE_DEBOUNCE_MS must be a positive integer");
+        info!(
+            target: logging::SERVER,
+            "Ignoring repeated toggles within {}ms", ms
+        );
+        debounce::Debouncer::new(Duration::from_millis(ms))
+    });
+
+    let max_response_bytes = env::var("MAX_RESPONSE_BYTES").ok().map(|bytes| {
+        bytes
+            .parse()
+            .ok()
+            .filter(|&bytes| bytes > 0)
+            .expect("MAX_RESPONSE_BYTES must be a positive integer")
+    });
+
+    let categorizer = match env::var("CATEGORY_KEYWORDS_PATH") {
+        Ok(path) => {
+            let categorizer = categorize::Categorizer::load(&path).unwrap_or_else(|e| {
+                panic!("Failed to read CATEGORY_KEYWORDS_PATH {}: {}", path, e)
+            });
+            info!(
+                target: logging::SERVER,
+                "Loaded {} category keywords with {}",
+                categorizer.len(),
+                path
+            );
+            categorizer
+        }
+        Err(_) => categorize::Categorizer::built_in(),
+    };
+
+    let share_secret = env::var("SHARE_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty());
+    if share_secret.is_none() {
+        warn!(
+            target: logging::SERVER,
+            "SHARE_SECRET is not set; share tokens will stop working on restart"
+        );
+    }
+    let share_signer = share::Signer::new(share_secret.as_deref());
+
+    let audit_retention_days = env::var("AUDIT_RETENTION_DAYS")
+        .map(|days| {
+            days.parse()
+                .ok()
+                .filter(|&days| days > 0)
+                .expect("AUDIT_RETENTION_DAYS must be a positive integer")
+        })
+        .unwrap_or(30);
+
+    let backup = env::var("BACKUP_DIR").ok().map(|dir| backup::Config {
+        dir: dir.into(),
+        interval: Duration::from_secs(
+            env::var("BACKUP_INTERVAL_SECS")
+                .map(|secs| {
+                    secs.parse()
+                        .ok()
+                        .filter(|&secs| secs > 0)
+                        .expect("BACKUP_INTERVAL_SECS must be a positive integer")
+                })
+                .unwrap_or(24 * 60 * 60),
+        ),
+        keep: env::var("BACKUP_KEEP")
+            .map(|keep| {
+                keep.parse()
+                    .ok()
+                    .filter(|&keep| keep > 0)
+                    .expect("BACKUP_KEEP must be a positive integer")
+            })
+            .unwrap_or(7),
+    });
+    if let Some(backup) = backup {
+        info!(
+            target: logging::SERVER,
+            "Backing up the database to {} every {}s, keeping {}",
+            backup.dir.display(),
+            backup.interval.as_secs(),
+            backup.keep
+        );
+        actix_web::rt::spawn(backup::run(DB_PATH, backup));
+    }
+
+    let query_budget = env::var("QUERY_BUDGET")
+        .map(|budget| {
+            budget
+                .parse()
+                .expect("QUERY_BUDGET must be a non-negative integer")
+        })
+        .unwrap_or(10);
+
+    let server_header = env::var("SERVER_HEADER")
+        .ok()
+        .filter(|server| !server.is_empty());
+
+    let app_state = web::Data::new(AppState {
+        db: db::Pool::builder()
+            .max_size(pool_size)
+            .connection_timeout(Duration::from_secs(pool_timeout))
+            .build(DB_PATH),
+        api_key: env::var("API_KEY").ok().filter(|key| !key.is_empty()),
+        name_matching,
+        word_filter,
+        categorizer,
+        toggle_debounce,
+        max_response_bytes,
+        share_signer,
+    });
+    actix_web::rt::spawn(prune_audit_log(app_state.clone(), audit_retention_days));
+    actix_web::rt::spawn(prune_toggle_debounce(app_state.clone()));
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+            .wrap(Condition::new(read_only, from_fn(read_only::middleware)))
+            .wrap(security_headers::middleware(server_header.as_deref()))
+            .wrap(from_fn(debug::middleware))
+            .wrap(from_fn(pretty::middleware))
+            .wrap(from_fn(move |req, next| {
+                query_budget::middleware(query_budget, req, next)
+            }))
+            .wrap(from_fn(move |req, next| {
+                timeout::middleware(request_timeout, req, next)
+            }))
+            .wrap(Compress::default())
+            .wrap(from_fn(request_id::middleware))
+            .wrap(
+                Logger::new(r#"%a "%r" %s %b %Dms request_id=%{x-request-id}o"#)
+                    .log_target(logging::HTTP),
+            )
+            .route("/", web::get().to(index))
+            .route("/items", web::get().to(get_shopping_list))
+            .route("/items", web::post().to(add_item))
+            .route("/items", web::delete().to(clear_all))
+            .route("/autocomplete", web::get().to(autocomplete))
+            .route("/activity", web::get().to(get_activity))
+            .route("/trips", web::get().to(get_trips))
+            .route("/stores/{name}/layout", web::get().to(get_store_layout))
+            .route("/stores/{name}/layout", web::put().to(set_store_layout))
+            .route("/version", web::get().to(version))
+            .route("/history/readd", web::post().to(readd_item))
+            .route("/admin/vacuum", web::post().to(vacuum))
+            .route("/admin/repair", web::post().to(repair))
+            .route("/admin/connections", web::get().to(admin_connections))
+            .route("/settings", web::get().to(get_settings))
+            .route("/settings", web::put().to(put_settings))
+            .route("/lists", web::get().to(get_lists))
+            .route("/lists", web::post().to(create_list))
+            .route("/lists/summary", web::get().to(get_lists_summary))
+            .route("/lists/{id}", web::put().to(update_list))
+            .route("/lists/{id}/clone", web::post().to(clone_list))
+            .route("/items/export.md", web::get().to(export_markdown))
+            .route("/items/todos.ics", web::get().to(export_todos))
+            .route("/items/export.json", web::get().to(export_json))
+            .route("/items/import.json", web::post().to(import_json))
+            .route("/items/sections", web::get().to(get_sections))
+            .route("/items/due", web::get().to(get_due_items))
+            .route("/items/progress", web::get().to(get_progress))
+            .route("/items/expensive", web::get().to(get_expensive_items))
+            .route("/items/stale", web::get().to(get_stale_items))
+            .route("/items/complete-trip", web::post().to(complete_trip))
+            .route("/items/completed", web::delete().to(clear_completed))
+            // Fixed paths must be registered before the `/items/{id}` routes.
+            .route("/items/swap", web::put().to(swap_items))
+            .route("/items/dedupe", web::post().to(dedupe_items))
+            .route("/items/rebalance", web::post().to(rebalance_positions))
+            .route(
+                "/items/sort-alphabetical",
+                web::post().to(sort_alphabetical),
+            )
+            .route("/items/diff", web::post().to(diff_items))
+            .route("/items/reset", web::put().to(reset_items))
+            .route("/items/category", web::put().to(set_category_bulk))
+            .route("/items/quantities", web::put().to(set_quantities))
+            .route("/items/toggle-by-name", web::put().to(toggle_by_name))
+            .service(
+                web::resource("/items/import-text")
+                    .app_data(web::PayloadConfig::new(MAX_IMPORT_BYTES))
+                    .route(web::post().to(import_text)),
+            )
+            .route("/items/import-shared", web::post().to(import_shared))
+            .route(
+                "/items/category/{name}/shopped",
+                web::put().to(set_category_shopped),
+            )
+            .route(
+                "/items/by-barcode/{code}",
+                web::get().to(get_item_by_barcode),
+            )
+            .route("/items/{id}", web::get().to(get_item))
+            .route("/items/{id}", web::put().to(replace_item))
+            .route("/items/{id}/history", web::get().to(get_item_history))
+            .route("/items/{id}/children", web::get().to(get_item_children))
+            .route("/items/{id}/pairs", web::get().to(get_item_pairs))
+            .route("/items/{id}/share", web::get().to(share_item))
+            .route("/items/{id}/toggle", web::put().to(update_item_status))
+            .route("/items/{id}/shopped", web::put().to(set_item_shopped))
+            .route("/items/{id}/cas", web::put().to(compare_and_swap_shopped))
+            .route("/items/{id}/purchase", web::put().to(purchase_item))
+            .route("/items/{id}/pin", web::put().to(pin_item))
+            .route("/items/{id}/snooze", web::put().to(snooze_item))
+            .route("/items/{id}/unpin", web::put().to(unpin_item))
+            .route("/items/{id}/quantity", web::put().to(change_quantity))
+            .route("/items/{id}/move", web::put().to(move_item_to))
+            .route("/items/{id}/split", web::post().to(split_item))
+            .route(
+                "/items/{id}/aisle-position",
+                web::put().to(set_aisle_position),
+            )
+            .route("/items/{id}/to-top", web::put().to(move_item_to_top))
+            .route("/items/{id}/to-bottom", web::put().to(move_item_to_bottom))
+    })
+    .workers(workers);
+
+    let server = if let Ok(path) = env::var("BIND_UDS") {
+        remove_stale_socket(&path)?;
+        let server = server.bind_uds(&path)?;
+        // Owner and group only, so a reverse proxy sharing the group can connect.
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o660))?;
+        println!("Server running on unix socket {}", path);
+        server
+    } else {
+        let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
+        let port = env::var("PORT")
+            .unwrap_or_else(|_| "8080".to_string())
+            .parse()
+            .unwrap();
+        let server = match server.bind((host.as_str(), port)) {
+            Ok(server) => server,
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+                error!(
+                    target: logging::SERVER,
+                    "Port {} is already in use on {}; set PORT to a free port", port, host
+                );
+                std::process::exit(1);
+            }
+            Err(e) => return Err(e),
+        };
+        println!("Server running at http://{}:{}", host, port);
+        server
+    };
+
+    server.run().await
+}
+
+/// Removes a stale socket left at `path` by a previous run, refusing to
+/// touch anything that is not a socket.
+fn remove_stale_socket(path: &str) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            info!(target: logging::SERVER, "Removing stale socket {}", path);
+            std::fs::remove_file(path)
+        }
+        Ok(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("BIND_UDS path {} exists and is not a socket", path),
+        )),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{
+        TestRequest, call_and_read_body_json, call_service, init_service, read_body_json,
+    };
+
+    /// State over a fresh in-memory database. The pool holds a single
+    /// connection, so every request sees the same database.
+    fn test_state() -> web::Data<AppState> {
+        test_state_with(|_| {})
+    }
+
+    /// [`test_state`] with some settings changed.
+    fn test_state_with(configure: impl FnOnce(&mut AppState)) -> web::Data<AppState> {
+        let db = db::Pool::builder()
+            .max_size(1)
+            .connection_timeout(Duration::from_secs(1))
+            .build(":memory:");
+        db::init(&mut db.get().unwrap()).unwrap();
+        let mut state = AppState {
+            db,
+            api_key: Some("test-key".to_string()),
+            name_matching: db::NameMatching::CaseInsensitive,
+            word_filter: None,
+            categorizer: categorize::Categorizer::built_in(),
+            toggle_debounce: None,
+            max_response_bytes: None,
+            share_signer: share::Signer::new(Some("test-secret")),
+        };
+        configure(&mut state);
+        web::Data::new(state)
+    }
+
+    /// Inserts an unshopped item and returns its id.
+    fn insert(state: &AppState, name: &str, list_id: i64) -> i64 {
+        let conn = state.db.get().unwrap();
+        conn.execute(
+            "INSERT INTO shopping_items (name, is_shopped, list_id) VALUES (?1, 0, ?2)",
+            params![name, list_id],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn position(state: &AppState, id: i64) -> f64 {
+        state
+            .db
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT position FROM shopping_items WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn swapping_an_item_with_itself_is_rejected() {
+        let state = test_state();
+        let id = insert(&state, "milk", 1);
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items/swap", web::put().to(swap_items)),
+        )
+        .await;
+
+        let req = TestRequest::put()
+            .uri("/items/swap")
+            .set_json((id, id))
+            .to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(position(&state, id), 1.0);
+    }
+
+    #[actix_web::test]
+    async fn swapping_items_on_different_lists_is_rejected() {
+        let state = test_state();
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute("INSERT INTO lists (id, name) VALUES (2, 'Hardware')", [])
+            .unwrap();
+        let milk = insert(&state, "milk", 1);
+        insert(&state, "screws", 2);
+        let nails = insert(&state, "nails", 2);
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items/swap", web::put().to(swap_items)),
+        )
+        .await;
+
+        let req = TestRequest::put()
+            .uri("/items/swap")
+            .set_json((milk, nails))
+            .to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(position(&state, milk), 1.0);
+        assert_eq!(position(&state, nails), 2.0);
+    }
+
+    #[actix_web::test]
+    async fn pinned_items_come_first() {
+        let state = test_state();
+        let apples = insert(&state, "apples", 1);
+        let bread = insert(&state, "bread", 1);
+        let cheese = insert(&state, "cheese", 1);
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute(
+                "UPDATE shopping_items SET pinned = 1 WHERE id = ?1",
+                [cheese],
+            )
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items", web::get().to(get_shopping_list)),
+        )
+        .await;
+
+        for uri in ["/items", "/items?sort=id", "/items?sort=name"] {
+            let req = TestRequest::get().uri(uri).to_request();
+            let items: Vec<Value> = call_and_read_body_json(&app, req).await;
+            let ids: Vec<i64> = items
+                .iter()
+                .map(|item| item["id"].as_i64().unwrap())
+                .collect();
+            assert_eq!(ids, [cheese, apples, bread], "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn toggle_needs_no_content_type() {
+        let state = test_state();
+        let id = insert(&state, "milk", 1);
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items/{id}/toggle", web::put().to(update_item_status)),
+        )
+        .await;
+
+        let req = TestRequest::put()
+            .uri(&format!("/items/{}/toggle", id))
+            .to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn autocomplete_searches_the_name_indexes() {
+        let mut conn = db::open(":memory:").unwrap();
+        db::init(&mut conn).unwrap();
+
+        let (sql, pattern) = autocomplete_query("mi", db::NameMatching::CaseInsensitive);
+        let plan: Vec<String> = conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .unwrap()
+            .query_map(params![pattern, 10], |row| row.get(3))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+
+        for index in ["idx_items_name", "idx_history_name"] {
+            assert!(
+                plan.iter()
+                    .any(|step| step.starts_with("SEARCH") && step.contains(index)),
+                "{} not used: {:?}",
+                index,
+                plan
+            );
+        }
+    }
+
+    #[test]
+    fn dedupe_keeps_the_lowest_id() {
+        let state = test_state();
+        let milk = insert(&state, "Milk", 1);
+        insert(&state, "milk ", 1);
+        insert(&state, "MILK", 1);
+        // Same name, other list: not a duplicate.
+        insert(&state, "milk", 2);
+        let mut conn = state.db.get().unwrap();
+        conn.execute("UPDATE shopping_items SET quantity = id", [])
+            .unwrap();
+
+        let tx = conn.transaction().unwrap();
+        let merged = dedupe(
+            &tx,
+            db::NameMatching::CaseInsensitive,
+            ShoppedPolicy::KeepUnshopped,
+        )
+        .unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(merged, (1, 2));
+        let kept = fetch_item(&conn, milk as i32).unwrap().unwrap();
+        assert_eq!((kept.name.as_str(), kept.quantity), ("Milk", 6));
+    }
+
+    #[test]
+    fn quantity_delta_overflow_is_rejected() {
+        assert_eq!(apply_quantity_delta(i32::MAX - 1, 1), Ok(i32::MAX));
+        assert!(
+            apply_quantity_delta(i32::MAX, 1)
+                .unwrap_err()
+                .contains("overflow")
+        );
+        assert!(
+            apply_quantity_delta(1, i64::from(i32::MAX) + 1)
+                .unwrap_err()
+                .contains("between")
+        );
+    }
+
+    #[test]
+    fn quantity_delta_below_one_is_rejected() {
+        assert_eq!(apply_quantity_delta(3, -2), Ok(1));
+        assert!(apply_quantity_delta(3, -3).unwrap_err().contains("below 1"));
+        assert!(
+            apply_quantity_delta(3, i64::from(i32::MIN))
+                .unwrap_err()
+                .contains("below 1")
+        );
+    }
+
+    #[actix_web::test]
+    async fn price_filter_totals_price_times_quantity() {
+        let state = test_state();
+        let milk = insert(&state, "milk", 1);
+        let eggs = insert(&state, "eggs", 1);
+        insert(&state, "caviar", 1);
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute_batch(&format!(
+                "UPDATE shopping_items SET price_cents = 120, quantity = 2 WHERE id = {milk};
+                 UPDATE shopping_items SET price_cents = 30, quantity = 10 WHERE id = {eggs};
+                 UPDATE shopping_items SET price_cents = 9000 WHERE name = 'caviar';"
+            ))
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items", web::get().to(get_shopping_list)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/items?max_price_cents=1000&limit=1")
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.headers().get(X_TOTAL_PRICE_CENTS).unwrap(), "540");
+        let body: Value = read_body_json(res).await;
+
+        assert_eq!(body["total_price_cents"], 540);
+        assert_eq!(body["items"].as_array().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn oversized_list_links_to_a_page_with_the_same_filters() {
+        let state = test_state_with(|state| state.max_response_bytes = Some(1000));
+        for name in ["apples", "bread", "cheese", "dates"] {
+            insert(&state, name, 1);
+        }
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items", web::get().to(get_shopping_list)),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/items?sort=name&limit=50&offset=1&modified_since=2020-01-01T00%3A00%3A00Z")
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: Value = read_body_json(res).await;
+
+        assert_eq!(
+            body["next"],
+            "/items?sort=name&modified_since=2020-01-01T00%3A00%3A00Z&limit=2&offset=1"
+        );
+    }
+
+    #[actix_web::test]
+    async fn json_export_survives_an_import() {
+        let state = test_state();
+        let milk = insert(&state, "milk", 1);
+        let oats = insert(&state, "oats", 1);
+        insert(&state, "bread", 1);
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute_batch(&format!(
+                "UPDATE shopping_items SET quantity = 2, category = 'dairy', pinned = 1,
+                                          price_cents = 120, barcode = '4006381333931'
+                 WHERE id = {milk};
+                 UPDATE shopping_items SET parent_id = {milk}, position = 1.5,
+                                          is_shopped = 1, unit = 'kg', amount_milli = 500
+                 WHERE id = {oats};"
+            ))
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items/export.json", web::get().to(export_json))
+                .route("/items/import.json", web::post().to(import_json)),
+        )
+        .await;
+        let export = || TestRequest::get().uri("/items/export.json").to_request();
+
+        let before: Value = call_and_read_body_json(&app, export()).await;
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute("DELETE FROM shopping_items", [])
+            .unwrap();
+
+        let req = TestRequest::post()
+            .uri("/items/import.json")
+            .set_json(&before)
+            .to_request();
+        assert_eq!(
+            call_service(&app, req).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let req = TestRequest::post()
+            .uri("/items/import.json")
+            .insert_header((auth::X_API_KEY, "test-key"))
+            .set_json(&before)
+            .to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let after: Value = call_and_read_body_json(&app, export()).await;
+        assert_eq!(after["items"].as_array().unwrap().len(), 3);
+        assert_eq!(after["items"], before["items"]);
+    }
+
+    fn count(state: &AppState) -> i64 {
+        state
+            .db
+            .get()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM shopping_items", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn clearing_completed_items_can_be_previewed() {
+        let state = test_state();
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute("INSERT INTO lists (id, name) VALUES (2, 'Hardware')", [])
+            .unwrap();
+        for (name, list_id) in [("milk", 1), ("bread", 1), ("eggs", 1), ("nails", 2)] {
+            insert(&state, name, list_id);
+        }
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute(
+                "UPDATE shopping_items SET is_shopped = 1 WHERE name != 'eggs'",
+                [],
+            )
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items/completed", web::delete().to(clear_completed)),
+        )
+        .await;
+
+        let req = TestRequest::delete()
+            .uri("/items/completed?list_id=1&dry_run=true")
+            .to_request();
+        let body: Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(body, json!({ "would_delete": 2 }));
+        assert_eq!(count(&state), 4);
+
+        let req = TestRequest::delete()
+            .uri("/items/completed?list_id=1")
+            .to_request();
+        let body: Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(body, json!({ "deleted": 2 }));
+        assert_eq!(count(&state), 2);
+    }
+
+    #[actix_web::test]
+    async fn clearing_all_items_can_be_previewed() {
+        let state = test_state();
+        for name in ["milk", "bread", "eggs"] {
+            insert(&state, name, 1);
+        }
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items", web::delete().to(clear_all)),
+        )
+        .await;
+
+        let req = TestRequest::delete()
+            .uri("/items?dry_run=true")
+            .to_request();
+        let body: Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(body, json!({ "would_delete": 3 }));
+        assert_eq!(count(&state), 3);
+
+        let req = TestRequest::delete().uri("/items").to_request();
+        let body: Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(body, json!({ "deleted": 3 }));
+        assert_eq!(count(&state), 0);
+    }
+
+    #[actix_web::test]
+    async fn auto_uncategorized_items_can_be_checked_by_category() {
+        let state = test_state();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items", web::post().to(add_item))
+                .route(
+                    "/items/category/{name}/shopped",
+                    web::put().to(set_category_shopped),
+                ),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/items?auto_category=true")
+            .set_json(json!({ "name": "lightbulb", "is_shopped": false }))
+            .to_request();
+        let created: Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(created["category"], categorize::UNCATEGORIZED);
+
+        let req = TestRequest::put()
+            .uri("/items/category/uncategorized/shopped")
+            .set_json(json!({ "is_shopped": true }))
+            .to_request();
+        let body: Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(body, json!({ "updated": 1 }));
+    }
+
+    #[actix_web::test]
+    async fn a_panic_holding_a_connection_leaves_the_pool_usable() {
+        let state = test_state();
+        insert(&state, "milk", 1);
+        let db = state.db.clone();
+        let panicked = std::thread::spawn(move || {
+            let _conn = db.get().unwrap();
+            panic!("handler panicked while holding the only connection");
+        })
+        .join();
+        assert!(panicked.is_err());
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items", web::get().to(get_shopping_list)),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/items").to_request();
+        let res = call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let items: Value = read_body_json(res).await;
+        assert_eq!(items.as_array().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn cloned_lists_keep_their_sub_items() {
+        let state = test_state();
+        let pasta = insert(&state, "pasta night", 1);
+        let noodles = insert(&state, "noodles", 1);
+        insert(&state, "bread", 1);
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute(
+                "UPDATE shopping_items SET parent_id = ?1 WHERE id = ?2",
+                [pasta, noodles],
+            )
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/lists/{id}/clone", web::post().to(clone_list)),
+        )
+        .await;
+
+        let req = TestRequest::post().uri("/lists/1/clone").to_request();
+        let clone: Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(clone["item_count"], 3);
+
+        let conn = state.db.get().unwrap();
+        let parent_of = |name: &str| -> Option<String> {
+            conn.query_row(
+                "SELECT parent.name FROM shopping_items child
+                 LEFT JOIN shopping_items parent ON parent.id = child.parent_id
+                 WHERE child.name = ?1 AND child.list_id = ?2",
+                params![name, clone["id"].as_i64()],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(parent_of("noodles").as_deref(), Some("pasta night"));
+        assert_eq!(parent_of("bread"), None);
+        let copied_parent: i64 = conn
+            .query_row(
+                "SELECT parent_id FROM shopping_items WHERE name = 'noodles' AND list_id = ?1",
+                [clone["id"].as_i64()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_ne!(copied_parent, pasta);
+    }
+
+    #[actix_web::test]
+    async fn null_fields_get_the_defaults() {
+        let state = test_state();
+        state
+            .db
+            .get()
+            .unwrap()
+            .execute(
+                "UPDATE settings SET default_quantity = 2, default_category = 'groceries'",
+                [],
+            )
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items", web::post().to(add_item)),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/items")
+            .set_json(json!({
+                "name": "milk",
+                "is_shopped": false,
+                "quantity": null,
+                "category": null,
+            }))
+            .to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::OK);
+
+        let id = state
+            .db
+            .get()
+            .unwrap()
+            .query_row("SELECT id FROM shopping_items", [], |row| row.get(0))
+            .unwrap();
+        let item = fetch_item(&state.db.get().unwrap(), id).unwrap().unwrap();
+        assert_eq!(item.quantity, 2);
+        assert_eq!(item.category.as_deref(), Some("groceries"));
+    }
+
+    /// `POST /items/import.json` of `doc` with the admin key.
+    fn import_request(doc: Value) -> TestRequest {
+        TestRequest::post()
+            .uri("/items/import.json")
+            .insert_header((auth::X_API_KEY, "test-key"))
+            .set_json(doc)
+    }
+
+    #[actix_web::test]
+    async fn version_1_exports_are_upgraded_on_import() {
+        let state = test_state();
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items/import.json", web::post().to(import_json)),
+        )
+        .await;
+
+        let doc = json!([
+            { "id": 1, "name": "milk", "is_shopped": "true" },
+            { "id": 2, "name": "bread", "is_shopped": 0, "quantity": null },
+        ]);
+        let body: Value = call_and_read_body_json(&app, import_request(doc).to_request()).await;
+        assert_eq!(body, json!({ "imported": 2, "schema_version": 1 }));
+
+        let conn = state.db.get().unwrap();
+        let milk = fetch_item(&conn, 1).unwrap().unwrap();
+        let bread = fetch_item(&conn, 2).unwrap().unwrap();
+        assert!(milk.is_shopped);
+        assert!(!bread.is_shopped);
+        assert_eq!((milk.quantity, bread.quantity), (1, 1));
+    }
+
+    #[actix_web::test]
+    async fn imports_from_newer_versions_or_with_cycles_are_rejected() {
+        let state = test_state();
+        insert(&state, "eggs", 1);
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .route("/items/import.json", web::post().to(import_json)),
+        )
+        .await;
+
+        let doc = json!({ "schema_version": export::SCHEMA_VERSION + 1, "items": [] });
+        let res = call_service(&app, import_request(doc).to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let doc = json!({
+            "schema_version": export::SCHEMA_VERSION,
+            "items": [
+                { "id": 1, "name": "milk", "is_shopped": false, "parent_id": 2 },
+                { "id": 2, "name": "bread", "is_shopped": false, "parent_id": 1 },
+            ],
+        });
+        let res = call_service(&app, import_request(doc).to_request()).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: Value = read_body_json(res).await;
+        assert!(body["error"].as_str().unwrap().contains("cycle"));
+
+        // Nothing was replaced.
+        let conn = state.db.get().unwrap();
+        let names: Vec<String> = conn
+            .prepare("SELECT name FROM shopping_items")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(names, ["eggs"]);
+    }
 }
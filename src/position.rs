@@ -0,0 +1,91 @@
+use rusqlite::{OptionalExtension, Transaction, params};
+
+/// Smallest gap left between neighbouring positions before a list is
+/// renumbered. Midpoints of `f64`s lose precision long before they collide,
+/// so this is far from the float limit.
+const MIN_GAP: f64 = 1e-6;
+
+/// Picks a position that places an item at 0-based `index` among the other
+/// items of `list_id` (leaving out `moving`, the item being placed, if it is
+/// already on the list). Only the placed item needs writing, except when the
+/// neighbours are too close and the list is rebalanced first.
+///
+/// Returns `Ok(Err(count))` if `index` is beyond the end of the list.
+pub fn at_index(
+    tx: &Transaction,
+    list_id: i64,
+    index: i64,
+    moving: Option<i32>,
+) -> rusqlite::Result<Result<f64, i64>> {
+    let count: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM shopping_items WHERE list_id = ?1 AND id IS NOT ?2",
+        params![list_id, moving],
+        |row| row.get(0),
+    )?;
+    if !(0..=count).contains(&index) {
+        return Ok(Err(count));
+    }
+
+    let mut rebalanced = false;
+    loop {
+        let neighbour = |offset: i64| {
+            tx.query_row(
+                "SELECT position FROM shopping_items WHERE list_id = ?1 AND id IS NOT ?2
+                 ORDER BY position, id LIMIT 1 OFFSET ?3",
+                params![list_id, moving, offset],
+                |row| row.get::<_, f64>(0),
+            )
+            .optional()
+        };
+        let before = if index > 0 {
+            neighbour(index - 1)?
+        } else {
+            None
+        };
+        let after = neighbour(index)?;
+
+        let position = match (before, after) {
+            (None, None) => 1.0,
+            (Some(before), None) => before + 1.0,
+            (None, Some(after)) => after - 1.0,
+            (Some(before), Some(after)) if after - before >= MIN_GAP || rebalanced => {
+                (before + after) / 2.0
+            }
+            (Some(_), Some(_)) => {
+                rebalance(tx, list_id)?;
+                rebalanced = true;
+                continue;
+            }
+        };
+        return Ok(Ok(position));
+    }
+}
+
+/// Renumbers the items of `list_id` to 1, 2, 3, ... keeping their order.
+pub fn rebalance(tx: &Transaction, list_id: i64) -> rusqlite::Result<usize> {
+    renumber(tx, list_id, "position, id")
+}
+
+/// Renumbers the items of `list_id` so they are stored in name order,
+/// ignoring case and accents like `?sort=name` does.
+pub fn sort_by_name(tx: &Transaction, list_id: i64) -> rusqlite::Result<usize> {
+    // Must match db::NAME_COLLATION.
+    renumber(tx, list_id, "name COLLATE NAME_NOCASE, id")
+}
+
+/// Gives the items of `list_id` positions 1, 2, 3, ... in `order`.
+fn renumber(tx: &Transaction, list_id: i64, order: &str) -> rusqlite::Result<usize> {
+    let ids = tx
+        .prepare(&format!(
+            "SELECT id FROM shopping_items WHERE list_id = ?1 ORDER BY {}",
+            order
+        ))?
+        .query_map([list_id], |row| row.get::<_, i32>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = tx.prepare("UPDATE shopping_items SET position = ?2 WHERE id = ?1")?;
+    for (rank, id) in ids.iter().enumerate() {
+        stmt.execute(params![id, rank as f64 + 1.0])?;
+    }
+    Ok(ids.len())
+}
@@ -0,0 +1,64 @@
+use actix_web::body::{self, BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::{self, HeaderName};
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+use serde::Deserialize;
+
+const X_PRETTY: HeaderName = HeaderName::from_static("x-pretty");
+
+#[derive(Debug, Deserialize)]
+struct PrettyQuery {
+    pretty: Option<String>,
+}
+
+pub fn is_true(value: &str) -> bool {
+    matches!(value, "true" | "1")
+}
+
+/// Whether a `GET` asked for `?pretty=true` or sent `X-Pretty: true`.
+fn wants_pretty(req: &ServiceRequest) -> bool {
+    if req.method() != Method::GET {
+        return false;
+    }
+    let header = req
+        .headers()
+        .get(X_PRETTY)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(is_true);
+    header
+        || web::Query::<PrettyQuery>::from_query(req.query_string())
+            .is_ok_and(|query| query.pretty.as_deref().is_some_and(is_true))
+}
+
+/// Re-indents JSON responses for people reading them in a terminal. The
+/// whole body is buffered first and object keys come out sorted, so this is
+/// meant for debugging, not for large streamed lists.
+pub async fn middleware<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B, BoxBody>>, Error> {
+    let pretty = wants_pretty(&req);
+    let res = next.call(req).await?;
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !pretty || !is_json {
+        return Ok(res.map_into_left_body());
+    }
+
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = body::to_bytes(body).await.map_err(Into::into)?;
+    // Bodies that aren't valid JSON after all are passed on as they are.
+    let bytes = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(value) => serde_json::to_vec_pretty(&value).map_or(bytes, Into::into),
+        Err(_) => bytes,
+    };
+    let mut res = res.set_body(BoxBody::new(bytes));
+    res.headers_mut().remove(header::CONTENT_LENGTH);
+    Ok(ServiceResponse::new(req, res).map_into_right_body())
+}
@@ -0,0 +1,245 @@
+use crate::ShoppingItem;
+
+/// Describes how much of an item to buy, followed by its name, the way the
+/// text exports show it: "apple", "3 apples", "2 L milk", "0.5 kg flour".
+///
+/// A single item is just its name. More than one gets a count and, when no
+/// unit is given, the plural of the name; names of things that aren't
+/// counted, like "milk", are written as "3 × milk" instead. Units are
+/// written as stored since they're usually symbols like "kg" or "L", and
+/// the name is left alone. A measured `amount_milli` takes the place of the
+/// count, prefixed with "2 × " when more than one is wanted.
+pub fn label(item: &ShoppingItem) -> String {
+    let unit = item
+        .unit
+        .as_deref()
+        .map(str::trim)
+        .filter(|unit| !unit.is_empty());
+    let times = if item.quantity > 1 {
+        format!("{} × ", item.quantity)
+    } else {
+        String::new()
+    };
+
+    match (item.amount_milli, unit) {
+        (Some(amount), Some(unit)) => {
+            format!("{}{} {} {}", times, format_milli(amount), unit, item.name)
+        }
+        (None, Some(unit)) => format!("{} {} {}", item.quantity, unit, item.name),
+        (Some(1000), None) => format!("{}{}", times, item.name),
+        (Some(amount), None) => format!("{}{}", times, counted(&format_milli(amount), &item.name)),
+        (None, None) if item.quantity > 1 => counted(&item.quantity.to_string(), &item.name),
+        (None, None) => item.name.clone(),
+    }
+}
+
+/// `count` of `name`: "3 apples", or "3 × milk" for names that have no
+/// plural.
+fn counted(count: &str, name: &str) -> String {
+    if is_uncountable(name) {
+        format!("{} × {}", count, name)
+    } else {
+        format!("{} {}", count, pluralize(name))
+    }
+}
+
+/// Groceries usually bought by amount rather than counted, going by the
+/// last word of the name.
+const UNCOUNTABLE: &[&str] = &[
+    "bacon",
+    "beef",
+    "bread",
+    "broccoli",
+    "butter",
+    "cereal",
+    "cheese",
+    "chocolate",
+    "coffee",
+    "cream",
+    "detergent",
+    "fish",
+    "flour",
+    "foil",
+    "garlic",
+    "ginger",
+    "granola",
+    "ham",
+    "honey",
+    "jam",
+    "juice",
+    "ketchup",
+    "lettuce",
+    "mayonnaise",
+    "meat",
+    "milk",
+    "mince",
+    "muesli",
+    "mustard",
+    "oil",
+    "pasta",
+    "pepper",
+    "pork",
+    "rice",
+    "salt",
+    "shampoo",
+    "soap",
+    "spinach",
+    "sugar",
+    "tea",
+    "tofu",
+    "toothpaste",
+    "vinegar",
+    "water",
+    "yoghurt",
+    "yogurt",
+];
+
+/// Plurals that don't follow the suffix rules in [`pluralize`].
+const IRREGULAR: &[(&str, &str)] = &[
+    ("child", "children"),
+    ("half", "halves"),
+    ("knife", "knives"),
+    ("leaf", "leaves"),
+    ("loaf", "loaves"),
+    ("mango", "mangoes"),
+    ("potato", "potatoes"),
+    ("shelf", "shelves"),
+    ("tomato", "tomatoes"),
+];
+
+fn last_word(lower: &str) -> &str {
+    lower.rsplit(' ').next().unwrap_or(lower)
+}
+
+fn is_uncountable(name: &str) -> bool {
+    UNCOUNTABLE.contains(&last_word(&name.to_lowercase()))
+}
+
+/// English plural of a name, going by its last word: "apple" -> "apples",
+/// "peach" -> "peaches", "cherry" -> "cherries", "knife" -> "knives". Names
+/// that already end in a plural "s", like "oats", are left alone.
+pub fn pluralize(name: &str) -> String {
+    let lower = name.to_lowercase();
+    let Some(last) = lower.chars().last().filter(char::is_ascii_alphabetic) else {
+        return name.to_string();
+    };
+
+    if let Some(&(singular, plural)) = IRREGULAR
+        .iter()
+        .find(|(singular, _)| last_word(&lower) == *singular)
+    {
+        let shared = singular
+            .bytes()
+            .zip(plural.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if let Some(stem) = name.get(..name.len() - singular.len() + shared) {
+            return format!("{}{}", stem, suffix(name, &plural[shared..]));
+        }
+    }
+    if lower.ends_with("ss")
+        || lower.ends_with("sh")
+        || lower.ends_with("ch")
+        || last == 'x'
+        || last == 'z'
+    {
+        return format!("{}{}", name, suffix(name, "es"));
+    }
+    if last == 's' {
+        return name.to_string();
+    }
+    let before_y = lower.strip_suffix('y').and_then(|stem| stem.chars().last());
+    if before_y.is_some_and(|c| c.is_ascii_alphabetic() && !"aeiou".contains(c)) {
+        return format!("{}{}", &name[..name.len() - 1], suffix(name, "ies"));
+    }
+    format!("{}{}", name, suffix(name, "s"))
+}
+
+/// Matches the suffix to a name written in capitals, so "EGG" becomes
+/// "EGGS" rather than "EGGs".
+fn suffix(name: &str, suffix: &str) -> String {
+    let shouting = name.chars().any(char::is_alphabetic) && !name.chars().any(char::is_lowercase);
+    if shouting {
+        suffix.to_uppercase()
+    } else {
+        suffix.to_string()
+    }
+}
+
+/// Writes thousandths as a decimal without trailing zeros: 500 -> "0.5",
+/// 2000 -> "2".
+fn format_milli(milli: i64) -> String {
+    let whole = milli / 1000;
+    let fraction = milli % 1000;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{:03}", fraction);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(
+        name: &str,
+        quantity: i32,
+        unit: Option<&str>,
+        amount_milli: Option<i64>,
+    ) -> ShoppingItem {
+        serde_json::from_value(json!({
+            "name": name,
+            "is_shopped": false,
+            "quantity": quantity,
+            "unit": unit,
+            "amount_milli": amount_milli,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn single_items_are_just_their_name() {
+        assert_eq!(label(&item("apple", 1, None, None)), "apple");
+        assert_eq!(label(&item("milk", 1, None, None)), "milk");
+        assert_eq!(label(&item("flour", 1, None, Some(1000))), "flour");
+    }
+
+    #[test]
+    fn several_items_are_counted_in_the_plural() {
+        assert_eq!(label(&item("apple", 3, None, None)), "3 apples");
+        assert_eq!(label(&item("peach", 2, None, None)), "2 peaches");
+        assert_eq!(label(&item("cherry", 2, None, None)), "2 cherries");
+        assert_eq!(label(&item("Knife", 2, None, None)), "2 Knives");
+        assert_eq!(label(&item("TOMATO", 4, None, None)), "4 TOMATOES");
+        assert_eq!(label(&item("oats", 2, None, None)), "2 oats");
+        assert_eq!(label(&item("apple", 1, None, Some(2500))), "2.5 apples");
+    }
+
+    #[test]
+    fn uncountable_names_keep_their_form() {
+        assert_eq!(label(&item("Milk", 3, None, None)), "3 × Milk");
+        assert_eq!(
+            label(&item("apple juice", 2, None, None)),
+            "2 × apple juice"
+        );
+        assert_eq!(pluralize("rice"), "rices");
+        assert!(is_uncountable("Basmati Rice"));
+    }
+
+    #[test]
+    fn units_are_written_before_the_unchanged_name() {
+        assert_eq!(label(&item("milk", 1, Some("L"), None)), "1 L milk");
+        assert_eq!(label(&item("egg", 2, Some("box"), None)), "2 box egg");
+        assert_eq!(
+            label(&item("flour", 1, Some("kg"), Some(500))),
+            "0.5 kg flour"
+        );
+        assert_eq!(
+            label(&item("flour", 2, Some("kg"), Some(500))),
+            "2 × 0.5 kg flour"
+        );
+        assert_eq!(label(&item("milk", 1, Some("  "), None)), "milk");
+    }
+}
@@ -0,0 +1,37 @@
+use crate::{db, logging, request_id};
+use actix_web::Error;
+use actix_web::HttpMessage;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use log::warn;
+
+/// Counts the statements each request runs and logs a warning when there
+/// are more than `budget`, set by `QUERY_BUDGET`, to catch queries issued
+/// once per item. The request itself is answered as usual.
+///
+/// The counter is left in the request extensions, where the `?debug=true`
+/// envelope picks it up.
+pub async fn middleware<B: MessageBody>(
+    budget: u64,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let counter = db::QueryCounter::default();
+    req.extensions_mut().insert(counter.clone());
+    let request_id = request_id::of(req.request());
+    let route = format!("{} {}", req.method(), req.path());
+
+    let res = db::count_queries(&counter, next.call(req)).await;
+    if counter.get() > budget {
+        warn!(
+            target: logging::DB,
+            "[{}] {} ran {} queries, over the budget of {}",
+            request_id,
+            route,
+            counter.get(),
+            budget
+        );
+    }
+    res
+}
@@ -0,0 +1,21 @@
+use crate::error::ApiError;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{Error, ResponseError};
+
+/// Rejects every request that could modify data with `403 Forbidden`, for
+/// maintenance windows and public demos. Enabled by `READ_ONLY`.
+pub async fn middleware<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let response =
+        ApiError::Forbidden("The service is in read-only mode".to_string()).error_response();
+    Ok(req.into_response(response).map_into_right_body())
+}
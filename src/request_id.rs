@@ -0,0 +1,119 @@
+use crate::error::ApiError;
+use crate::logging;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use log::{error, warn};
+use std::fmt;
+use std::future::{Ready, ready};
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id we accept before generating our own.
+const MAX_LEN: usize = 128;
+
+/// Correlation id for a single request, taken from the `X-Request-Id` header
+/// or generated when the client did not send one.
+#[derive(Debug, Clone)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?.trim();
+        let valid = !value.is_empty()
+            && value.len() <= MAX_LEN
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c));
+        valid.then(|| RequestId(value.to_string()))
+    }
+
+    /// Generates a random (version 4) UUID.
+    fn generate() -> Self {
+        let mut bytes: [u8; 16] = rand::random();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        RequestId(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32]
+        ))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(of(req)))
+    }
+}
+
+/// Returns the id attached by [`middleware`], or `-` outside of it.
+pub fn of(req: &HttpRequest) -> RequestId {
+    req.extensions()
+        .get::<RequestId>()
+        .cloned()
+        .unwrap_or_else(|| RequestId("-".to_string()))
+}
+
+fn log_error(request_id: &RequestId, status: StatusCode, err: &Error) {
+    // Database failures go to their own target, so they can be kept while
+    // the HTTP logs are silenced.
+    let target = match err.as_error::<ApiError>() {
+        Some(ApiError::Database(_) | ApiError::Unavailable(_)) => logging::DB,
+        _ => logging::HTTP,
+    };
+    if status == StatusCode::SERVICE_UNAVAILABLE {
+        warn!(target: target, "[{}] {}", request_id, err);
+    } else if status.is_server_error() {
+        error!(target: target, "[{}] {}", request_id, err);
+    }
+}
+
+/// Attaches a [`RequestId`] to the request extensions and echoes it back in
+/// the `X-Request-Id` response header. Server errors returned by handlers
+/// are logged here, with the id.
+pub async fn middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let request_id = req
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(RequestId::generate);
+    req.extensions_mut().insert(request_id.clone());
+
+    let mut res = match next.call(req).await {
+        Ok(res) => res,
+        Err(err) => {
+            // Raised by middleware such as the request timeout; actix turns
+            // it into the response.
+            log_error(&request_id, err.as_response_error().status_code(), &err);
+            return Err(err);
+        }
+    };
+    if let Some(err) = res.response().error() {
+        log_error(&request_id, res.status(), err);
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id.0) {
+        res.headers_mut().insert(X_REQUEST_ID, value);
+    }
+    Ok(res)
+}
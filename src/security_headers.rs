@@ -0,0 +1,53 @@
+use actix_web::http::header;
+use actix_web::middleware::DefaultHeaders;
+
+/// Headers added to every response that doesn't set them itself: no MIME
+/// sniffing, no framing, and a `Server` header only when `SERVER_HEADER`
+/// names one. Actix sends none by default, so nothing gives the stack away.
+pub fn middleware(server: Option<&str>) -> DefaultHeaders {
+    let headers = DefaultHeaders::new()
+        .add((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .add((header::X_FRAME_OPTIONS, "DENY"));
+    match server {
+        Some(server) => headers.add((header::SERVER, server)),
+        None => headers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{TestRequest, call_service, init_service};
+    use actix_web::{App, HttpResponse, web};
+
+    #[actix_web::test]
+    async fn responses_carry_the_headers() {
+        let app = init_service(
+            App::new()
+                .wrap(middleware(Some("shoppinglist")))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let res = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        let headers = res.headers();
+        assert_eq!(
+            headers.get(header::X_CONTENT_TYPE_OPTIONS).unwrap(),
+            "nosniff"
+        );
+        assert_eq!(headers.get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(headers.get(header::SERVER).unwrap(), "shoppinglist");
+    }
+
+    #[actix_web::test]
+    async fn server_header_is_only_sent_when_configured() {
+        let app = init_service(
+            App::new()
+                .wrap(middleware(None))
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let res = call_service(&app, TestRequest::get().uri("/").to_request()).await;
+        assert!(res.headers().get(header::SERVER).is_none());
+        assert_eq!(res.headers().get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+    }
+}
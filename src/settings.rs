@@ -0,0 +1,48 @@
+use rusqlite::{Connection, params};
+use serde::{Deserialize, Serialize};
+
+/// Household-wide preferences, stored in the single-row `settings` table.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Settings {
+    /// Quantity for new items that don't specify one.
+    pub default_quantity: i32,
+    /// Category for new items that don't specify one.
+    pub default_category: Option<String>,
+}
+
+impl Settings {
+    pub fn validate(&mut self) -> Result<(), String> {
+        if self.default_quantity < 1 {
+            return Err("default_quantity must be at least 1".to_string());
+        }
+        if let Some(category) = &self.default_category {
+            let category = category.trim();
+            if category.is_empty() {
+                return Err("default_category must not be empty, use null instead".to_string());
+            }
+            self.default_category = Some(category.to_string());
+        }
+        Ok(())
+    }
+}
+
+pub fn load(conn: &Connection) -> rusqlite::Result<Settings> {
+    conn.query_row(
+        "SELECT default_quantity, default_category FROM settings WHERE id = 1",
+        [],
+        |row| {
+            Ok(Settings {
+                default_quantity: row.get(0)?,
+                default_category: row.get(1)?,
+            })
+        },
+    )
+}
+
+pub fn save(conn: &Connection, settings: &Settings) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE settings SET default_quantity = ?1, default_category = ?2 WHERE id = 1",
+        params![settings.default_quantity, settings.default_category],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::OffsetDateTime;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a share token can be imported after it was issued.
+pub const TOKEN_TTL: time::Duration = time::Duration::days(7);
+
+/// The part of an item another list gets when it is shared.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedItem {
+    pub name: String,
+    pub quantity: i32,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    #[serde(flatten)]
+    item: SharedItem,
+    /// Unix time after which the token is refused.
+    exp: i64,
+}
+
+/// Signs share tokens with `SHARE_SECRET`, or with a random key when that is
+/// unset, in which case tokens stop working when the server restarts.
+pub struct Signer {
+    key: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(secret: Option<&str>) -> Self {
+        let key = match secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => rand::random::<[u8; 32]>().to_vec(),
+        };
+        Signer { key }
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    /// Encodes the item as `payload.signature`, both base64url, valid for
+    /// [`TOKEN_TTL`] from `now`.
+    pub fn token(&self, item: SharedItem, now: OffsetDateTime) -> String {
+        let claims = Claims {
+            item,
+            exp: (now + TOKEN_TTL).unix_timestamp(),
+        };
+        let json = serde_json::to_vec(&claims).expect("claims serialize");
+        let payload = URL_SAFE_NO_PAD.encode(json);
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    /// Checks the token's signature and expiry and returns the item in it.
+    pub fn verify(&self, token: &str, now: OffsetDateTime) -> Result<SharedItem, String> {
+        let invalid = || "Share token is invalid".to_string();
+        let (payload, signature) = token.trim().split_once('.').ok_or_else(invalid)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+
+        let json = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let claims: Claims = serde_json::from_slice(&json).map_err(|_| invalid())?;
+        if claims.exp <= now.unix_timestamp() {
+            return Err("Share token has expired".to_string());
+        }
+        Ok(claims.item)
+    }
+}
@@ -0,0 +1,52 @@
+use rusqlite::{Connection, Transaction, params};
+
+/// Order of `GET /items?route=`: the store's category sequence, categories
+/// it doesn't list last, then by name. The `?` is bound to the store.
+// Must match db::NAME_COLLATION.
+pub const ROUTE_ORDER: &str = "(SELECT sequence FROM store_layouts \
+                               WHERE store = ? AND category = shopping_items.category) \
+                               NULLS LAST, name COLLATE NAME_NOCASE, id";
+
+/// Most categories one layout may list.
+pub const MAX_LAYOUT_CATEGORIES: usize = 200;
+
+/// The categories of `store` in the order they are walked past, empty when
+/// the store has no layout.
+pub fn layout(conn: &Connection, store: &str) -> rusqlite::Result<Vec<String>> {
+    conn.prepare("SELECT category FROM store_layouts WHERE store = ?1 ORDER BY sequence")?
+        .query_map([store], |row| row.get(0))?
+        .collect()
+}
+
+/// Replaces the layout of `store`. An empty list removes it.
+pub fn set_layout(tx: &Transaction, store: &str, categories: &[String]) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM store_layouts WHERE store = ?1", [store])?;
+    let mut stmt =
+        tx.prepare("INSERT INTO store_layouts (store, category, sequence) VALUES (?1, ?2, ?3)")?;
+    for (sequence, category) in categories.iter().enumerate() {
+        stmt.execute(params![store, category, sequence as i64])?;
+    }
+    Ok(())
+}
+
+/// Trims the categories and rejects empty, repeated or too many of them.
+pub fn validate_layout(categories: &[String]) -> Result<Vec<String>, String> {
+    if categories.len() > MAX_LAYOUT_CATEGORIES {
+        return Err(format!(
+            "A layout may list at most {} categories",
+            MAX_LAYOUT_CATEGORIES
+        ));
+    }
+    let mut validated: Vec<String> = Vec::with_capacity(categories.len());
+    for category in categories {
+        let category = category.trim();
+        if category.is_empty() {
+            return Err("categories must not be empty".to_string());
+        }
+        if validated.iter().any(|seen| seen == category) {
+            return Err(format!("category '{}' is listed twice", category));
+        }
+        validated.push(category.to_string());
+    }
+    Ok(validated)
+}
@@ -0,0 +1,110 @@
+use crate::request_id::RequestId;
+use crate::{Filter, ITEM_COLUMNS, db, item_from_row, logging};
+use actix_web::HttpResponse;
+use actix_web::http::header::{self, ContentType};
+use actix_web::rt::task;
+use actix_web::web::Bytes;
+use futures_core::Stream;
+use log::{error, info};
+use rusqlite::params_from_iter;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// Items serialized into each chunk of the response body.
+const ITEMS_PER_CHUNK: usize = 64;
+
+/// Response body fed by a channel from the blocking task reading the rows.
+struct ChunkStream(mpsc::Receiver<Result<Bytes, io::Error>>);
+
+impl Stream for ChunkStream {
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+/// Responds with the JSON array of items written incrementally as rows are
+/// read from the cursor, so the whole list is never held in memory.
+///
+/// The status is sent before the first row is read; a database error midway
+/// is logged and aborts the body, leaving the client with truncated JSON.
+pub fn items(
+    conn: db::PooledConnection,
+    filter: Filter,
+    order_by: &'static str,
+    relative_time: bool,
+    request_id: RequestId,
+) -> HttpResponse {
+    let (tx, rx) = mpsc::channel(4);
+
+    task::spawn_blocking(move || {
+        let result = write_items(&conn, &filter, order_by, relative_time, &tx);
+        match result {
+            Ok(count) => info!(
+                target: logging::API,
+                "[{}] Streamed {} items", request_id, count
+            ),
+            Err(e) => {
+                error!(
+                    target: logging::API,
+                    "[{}] Failed to stream shopping items: {:?}", request_id, e
+                );
+                let _ = tx.blocking_send(Err(io::Error::other(e.to_string())));
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .insert_header((header::VARY, "Accept"))
+        .content_type(ContentType::json())
+        .streaming(ChunkStream(rx))
+}
+
+fn write_items(
+    conn: &rusqlite::Connection,
+    filter: &Filter,
+    order_by: &str,
+    relative_time: bool,
+    tx: &mpsc::Sender<Result<Bytes, io::Error>>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let now = time::OffsetDateTime::now_utc();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM shopping_items{} ORDER BY {}{}",
+        ITEM_COLUMNS,
+        filter.where_clause(),
+        order_by,
+        filter.page_clause()
+    ))?;
+    let rows = stmt.query_map(params_from_iter(filter.query_params()), item_from_row)?;
+
+    let mut chunk = b"[".to_vec();
+    let mut count = 0;
+    for item in rows {
+        if count > 0 {
+            chunk.push(b',');
+        }
+        let mut item = item?;
+        if relative_time {
+            item.add_relative_times(now);
+        }
+        serde_json::to_writer(&mut chunk, &item)?;
+        count += 1;
+
+        if count % ITEMS_PER_CHUNK == 0 && !send(tx, &mut chunk) {
+            // The client went away.
+            return Ok(count);
+        }
+    }
+    chunk.push(b']');
+    send(tx, &mut chunk);
+    Ok(count)
+}
+
+/// Sends the buffered bytes, returning `false` once the receiver is gone.
+fn send(tx: &mpsc::Sender<Result<Bytes, io::Error>>, chunk: &mut Vec<u8>) -> bool {
+    let bytes = Bytes::from(std::mem::take(chunk));
+    tx.blocking_send(Ok(bytes)).is_ok()
+}
@@ -0,0 +1,91 @@
+use crate::db;
+use crate::error::ApiError;
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::rt::time;
+use std::time::{Duration, Instant};
+
+/// Answers with `503 Service Unavailable` once a request has taken longer
+/// than `limit`, set by `REQUEST_TIMEOUT_MS`.
+///
+/// The handler is dropped at its next await point, such as while reading a
+/// slow client's body. Database calls run synchronously, so a slow
+/// statement is interrupted by [`db::with_deadline`] instead and its error
+/// turned into the same 503. Waiting for a connection or a lock is bounded
+/// by the pool's checkout timeout and SQLite's busy timeout.
+pub async fn middleware<B: MessageBody>(
+    limit: Duration,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let deadline = Instant::now() + limit;
+    // Routing needs the only reference to the request, so there is no
+    // request to build a response with here. actix renders the error as a
+    // response instead.
+    match time::timeout(limit, db::with_deadline(deadline, next.call(req))).await {
+        Ok(Ok(res)) if interrupted(&res) => Err(ApiError::Timeout(limit).into()),
+        Ok(res) => res,
+        Err(_) => Err(ApiError::Timeout(limit).into()),
+    }
+}
+
+/// Whether the handler failed on a statement interrupted at the deadline.
+fn interrupted<B>(res: &ServiceResponse<B>) -> bool {
+    res.response()
+        .error()
+        .and_then(|e| e.as_error::<ApiError>())
+        .is_some_and(|e| matches!(e, ApiError::Database(e) if db::is_interrupted(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{TestRequest, init_service, try_call_service};
+    use actix_web::{App, HttpResponse, web};
+
+    /// Counts far enough to take seconds unless interrupted.
+    async fn count_slowly(db: web::Data<db::Pool>) -> Result<HttpResponse, ApiError> {
+        let count: i64 = db.get()?.query_row(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10000000)
+             SELECT count(*) FROM n",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(HttpResponse::Ok().json(count))
+    }
+
+    #[actix_web::test]
+    async fn slow_queries_are_interrupted() {
+        let limit = Duration::from_millis(100);
+        let db = db::Pool::builder().max_size(1).build(":memory:");
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(db.clone()))
+                .wrap(from_fn(move |req, next| middleware(limit, req, next)))
+                .route("/", web::get().to(count_slowly)),
+        )
+        .await;
+
+        let started = Instant::now();
+        let err = try_call_service(&app, TestRequest::get().uri("/").to_request())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.as_response_error().status_code(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert!(started.elapsed() < Duration::from_secs(1));
+        // The interrupted connection is still usable.
+        let one: i64 = db
+            .get()
+            .unwrap()
+            .query_row("SELECT 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(one, 1);
+    }
+}
@@ -0,0 +1,51 @@
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+use time::{Duration, OffsetDateTime, UtcOffset};
+
+/// Parses an RFC 3339 timestamp from a client.
+pub fn parse(value: &str) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(value.trim(), &Rfc3339).ok()
+}
+
+/// Formats a time the way the database stores timestamps (see
+/// `strftime('%Y-%m-%dT%H:%M:%fZ', 'now')` in the SQL): UTC with millisecond
+/// precision, so stored values compare correctly as strings.
+pub fn format(value: OffsetDateTime) -> String {
+    value
+        .to_offset(UtcOffset::UTC)
+        .format(format_description!(
+            "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z"
+        ))
+        .expect("timestamp is formattable")
+}
+
+/// Parses a client timestamp and brings it into the stored form, for use in
+/// comparisons against timestamp columns.
+pub fn normalize(value: &str) -> Result<String, String> {
+    parse(value)
+        .map(format)
+        .ok_or_else(|| format!("'{}' is not a valid RFC 3339 timestamp", value))
+}
+
+/// Describes how long before `now` a time was, like "3 days ago", in the
+/// largest whole unit up to weeks. Times in the future read "just now".
+pub fn ago(then: OffsetDateTime, now: OffsetDateTime) -> String {
+    let elapsed = now - then;
+    if elapsed < Duration::seconds(1) {
+        return "just now".to_string();
+    }
+
+    let (count, unit) = if elapsed < Duration::MINUTE {
+        (elapsed.whole_seconds(), "second")
+    } else if elapsed < Duration::HOUR {
+        (elapsed.whole_minutes(), "minute")
+    } else if elapsed < Duration::DAY {
+        (elapsed.whole_hours(), "hour")
+    } else if elapsed < Duration::WEEK {
+        (elapsed.whole_days(), "day")
+    } else {
+        (elapsed.whole_weeks(), "week")
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
@@ -0,0 +1,155 @@
+use rusqlite::{Connection, OptionalExtension, Row, Transaction};
+use serde::Serialize;
+
+/// An item as it was when its trip was completed.
+#[derive(Debug, Serialize)]
+pub struct TripItem {
+    pub item_id: i64,
+    pub name: String,
+    pub quantity: i32,
+    pub quantity_bought: Option<i32>,
+    pub category: Option<String>,
+    pub unit: Option<String>,
+    pub amount_milli: Option<i64>,
+    /// What was paid for the line, or the estimated unit price times the
+    /// quantity when no price was recorded.
+    pub price_cents: Option<i64>,
+}
+
+/// A completed shopping trip, archived by `POST /items/complete-trip`.
+#[derive(Debug, Serialize)]
+pub struct Trip {
+    pub id: i64,
+    pub completed_at: String,
+    /// The list the trip was for, or `None` for all lists.
+    pub list_id: Option<i64>,
+    /// Sum of the items' prices, `None` when none had one.
+    pub total_cents: Option<i64>,
+    pub items: Vec<TripItem>,
+}
+
+/// Price of a shopped item's line: `paid_price_cents` is what the whole line
+/// cost, `price_cents` the estimate per unit. Matches the list totals.
+const LINE_PRICE: &str =
+    "COALESCE(paid_price_cents, price_cents * COALESCE(quantity_bought, quantity))";
+
+fn trip_item_from_row(row: &Row) -> rusqlite::Result<TripItem> {
+    Ok(TripItem {
+        item_id: row.get(0)?,
+        name: row.get(1)?,
+        quantity: row.get(2)?,
+        quantity_bought: row.get(3)?,
+        category: row.get(4)?,
+        unit: row.get(5)?,
+        amount_milli: row.get(6)?,
+        price_cents: row.get(7)?,
+    })
+}
+
+fn load(conn: &Connection, trip_id: i64) -> rusqlite::Result<Option<Trip>> {
+    let Some((completed_at, list_id, total_cents)) = conn
+        .query_row(
+            "SELECT completed_at, list_id, total_cents FROM trips WHERE id = ?1",
+            [trip_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let mut stmt = conn.prepare(
+        "SELECT item_id, name, quantity, quantity_bought, category, unit, amount_milli,
+                price_cents
+         FROM trip_items WHERE trip_id = ?1 ORDER BY id",
+    )?;
+    let items = stmt
+        .query_map([trip_id], trip_item_from_row)?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(Some(Trip {
+        id: trip_id,
+        completed_at,
+        list_id,
+        total_cents,
+        items,
+    }))
+}
+
+/// Archives the shopped items, of one list or of all, as a new trip.
+/// Returns `None` without writing anything when nothing is shopped.
+pub fn create(tx: &Transaction, list_id: Option<i64>) -> rusqlite::Result<Option<Trip>> {
+    let shopped: i64 = tx.query_row(
+        "SELECT COUNT(*) FROM shopping_items WHERE is_shopped AND (?1 IS NULL OR list_id = ?1)",
+        [list_id],
+        |row| row.get(0),
+    )?;
+    if shopped == 0 {
+        return Ok(None);
+    }
+
+    tx.execute(
+        &format!(
+            "INSERT INTO trips (completed_at, list_id, total_cents)
+             SELECT strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), ?1, SUM({})
+             FROM shopping_items WHERE is_shopped AND (?1 IS NULL OR list_id = ?1)",
+            LINE_PRICE
+        ),
+        [list_id],
+    )?;
+    let trip_id = tx.last_insert_rowid();
+    tx.execute(
+        &format!(
+            "INSERT INTO trip_items
+                 (trip_id, item_id, name, quantity, quantity_bought, category, unit,
+                  amount_milli, price_cents)
+             SELECT ?1, id, name, quantity, quantity_bought, category, unit, amount_milli, {}
+             FROM shopping_items WHERE is_shopped AND (?2 IS NULL OR list_id = ?2)
+             ORDER BY list_id, position, id",
+            LINE_PRICE
+        ),
+        rusqlite::params![trip_id, list_id],
+    )?;
+    load(tx, trip_id)
+}
+
+/// The latest `limit` trips, newest first.
+pub fn recent(conn: &Connection, limit: u32) -> rusqlite::Result<Vec<Trip>> {
+    let mut stmt = conn.prepare("SELECT id FROM trips ORDER BY id DESC LIMIT ?1")?;
+    let ids = stmt
+        .query_map([limit], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<i64>>>()?;
+    ids.into_iter()
+        .filter_map(|id| load(conn, id).transpose())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn estimated_prices_count_every_unit() {
+        let mut conn = db::open(":memory:").unwrap();
+        db::init(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO shopping_items (name, is_shopped, price_cents, quantity)
+                 VALUES ('eggs', 1, 30, 10);
+             INSERT INTO shopping_items (name, is_shopped, price_cents, quantity, quantity_bought)
+                 VALUES ('milk', 1, 120, 3, 2);
+             INSERT INTO shopping_items
+                     (name, is_shopped, price_cents, paid_price_cents, quantity, quantity_bought)
+                 VALUES ('cheese', 1, 400, 750, 2, 2);
+             INSERT INTO shopping_items (name, is_shopped, price_cents, quantity)
+                 VALUES ('caviar', 0, 9000, 1);",
+        )
+        .unwrap();
+
+        let tx = conn.transaction().unwrap();
+        let trip = create(&tx, None).unwrap().unwrap();
+        tx.commit().unwrap();
+
+        let prices: Vec<_> = trip.items.iter().map(|item| item.price_cents).collect();
+        assert_eq!(prices, [Some(300), Some(240), Some(750)]);
+        assert_eq!(trip.total_cents, Some(1290));
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+use std::{fs, io};
+
+/// Words that item names may not contain, loaded from `WORD_FILTER_PATH`.
+///
+/// Names are compared word by word, ignoring case, so "Scunthorpe sausages"
+/// isn't caught by a filter listing a word it merely contains.
+#[derive(Debug)]
+pub struct WordFilter {
+    words: HashSet<String>,
+}
+
+impl WordFilter {
+    /// Reads one word per line. Blank lines and lines starting with `#` are
+    /// skipped.
+    pub fn load(path: &str) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    fn parse(list: &str) -> Self {
+        let words = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_lowercase)
+            .collect();
+        WordFilter { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns the first word of `name` that is on the list.
+    pub fn blocked<'a>(&self, name: &'a str) -> Option<&'a str> {
+        name.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .find(|word| self.words.contains(&word.to_lowercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_listed_words_in_any_case() {
+        let filter = WordFilter::parse("# rude words\nbadword\n\n  Darn  \n");
+        assert_eq!(filter.len(), 2);
+        assert_eq!(filter.blocked("BadWord cookies"), Some("BadWord"));
+        assert_eq!(filter.blocked("milk, darn it"), Some("darn"));
+        assert_eq!(filter.blocked("milk"), None);
+    }
+
+    #[test]
+    fn ignores_words_inside_other_words() {
+        let filter = WordFilter::parse("cunt\nass\n");
+        assert_eq!(filter.blocked("Scunthorpe sausages"), None);
+        assert_eq!(filter.blocked("glass noodles"), None);
+        assert_eq!(filter.blocked("molasses"), None);
+    }
+}